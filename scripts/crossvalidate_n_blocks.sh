@@ -1,7 +1,9 @@
 #! /bin/bash
 
+# Usage: crossvalidate_n_blocks.sh <build url, e.g. http://aggregator/builds/42> <heights> [tolerance in seconds]
 URL=$1
 REQUIRED_HEIGHTS=$2
+TOLERANCE=${3:-1.0}
 
 while true
 do
@@ -16,4 +18,12 @@ do
 done
 
 
-curl -s $URL/validate/ipc?count=$REQUIRED_HEIGHTS | jq
+REPORT=$(curl -s "$URL/validate/ipc?count=$REQUIRED_HEIGHTS&tolerance=$TOLERANCE")
+echo "$REPORT" | jq
+
+if [ "$(echo "$REPORT" | jq '.passed')" != "true" ]; then
+    echo "Cross validation failed for nodes: $(echo "$REPORT" | jq -c '.failed_nodes')"
+    exit 1
+fi
+
+echo "Cross validation passed"
@@ -1,12 +1,15 @@
 use std::collections::{BTreeMap, HashMap};
 
 use petgraph::algo::{connected_components, is_cyclic_directed};
-// use petgraph::dot::Dot;
 use petgraph::prelude::*;
 use petgraph::{Directed, Graph};
 use serde::{Deserialize, Serialize};
 
-use crate::debugger_data::{CpnpCapturedData, DebuggerCpnpResponse};
+use crate::debugger_data::{
+    CpnpCapturedData, CpnpEvent, CpnpEventType, CpnpMessageType, DebuggerCpnpResponse,
+};
+
+use super::{analyze_propagation, deserialize_graph, serialize_graph, PropagationAnalytics};
 
 use crate::{AggregatorError, AggregatorResult};
 
@@ -27,12 +30,15 @@ pub struct CpnpLatencyAggregationData {
     pub latency_since_block_publication_seconds: f64,
 }
 
-// TODO: Fix serde stuff when dumping storage!!
 #[derive(Debug, Default, Serialize, Clone, Deserialize)]
 pub struct CpnpBlockPublication {
     #[serde(flatten)]
     pub node_latencies: BTreeMap<NodeIP, CpnpLatencyAggregationData>,
-    #[serde(skip)]
+    #[serde(
+        serialize_with = "serialize_graph",
+        deserialize_with = "deserialize_graph",
+        default
+    )]
     pub graph: MessageGraph,
     pub publish_time: u64,
     pub block_hash: String,
@@ -60,7 +66,20 @@ pub struct MessageGraphInfo {
     pub source_nodes: Vec<String>,
     pub graph_count: usize,
     pub is_graph_cyclic: bool,
+    /// Polled nodes the debuggers did not see receiving the block at all
     pub missing_nodes: Vec<String>,
+    #[serde(default)]
+    pub missing_node_count: usize,
+    /// Nodes that received the block after the receive deadline
+    #[serde(default)]
+    pub late_nodes: Vec<String>,
+    #[serde(default)]
+    pub late_node_count: usize,
+    /// Missing and late nodes together
+    #[serde(default)]
+    pub not_received_within_deadline_count: usize,
+    #[serde(default)]
+    pub propagation: PropagationAnalytics,
 }
 
 impl From<CpnpBlockPublication> for CpnpBlockPublicationFlattened {
@@ -122,6 +141,8 @@ pub fn aggregate_first_receive(
     data: Vec<DebuggerCpnpResponse>,
     peer_id_to_tag_map: &BTreeMap<String, String>,
     tag_to_block_hash_map: &BTreeMap<String, String>,
+    cluster_nodes: &[String],
+    receive_deadline_micros: u64,
 ) -> AggregatorResult<(usize, BTreeMap<BlockHash, CpnpBlockPublication>)> {
     // println!("Data: {:#?}", data);
     // println!("PEER_ID TO TAG MAP: {:#?}", peer_id_to_tag_map);
@@ -138,37 +159,31 @@ pub fn aggregate_first_receive(
     // TODO: try to remove this additional iteration somehow as an optimization
     // Note: When we are sure the debugger timestamps are correct, we can remove this as by sorting by timestamp, the pusblish event will always
     //       preceed the receive events
-    let mut publish_messages: Vec<CpnpCapturedData> = events
-        .clone()
-        .into_iter()
-        .filter(|e| {
-            e.events
+    // The captured data is sorted, so the publish events are sorted by time as well
+    let publish_messages: Vec<(&CpnpCapturedData, &CpnpEvent)> = events
+        .iter()
+        .flat_map(|captured| {
+            captured
+                .events
                 .iter()
-                .filter(|cpnp_event| cpnp_event.r#type == *"publish_gossip")
-                .peekable()
-                .peek()
-                .is_some()
+                // only the block gossip is aggregated, not the pool diffs
+                .filter(|cpnp_event| {
+                    cpnp_event.r#type == CpnpEventType::PublishGossip
+                        && cpnp_event.msg.r#type == CpnpMessageType::NewState
+                })
+                .map(move |cpnp_event| (captured, cpnp_event))
         })
         .collect();
 
-    publish_messages.sort_by_key(|m| m.real_time_microseconds);
-
-    // println!("Publish messages: {:#?}", publish_messages);
-
-    // FIXME
-    let height = if !publish_messages.is_empty() {
-        publish_messages[0].events[0].msg.height
+    let height = if let Some((_, first_publish_event)) = publish_messages.first() {
+        first_publish_event.msg.height
     } else {
-        // println!("Error: Height");
         return Err(AggregatorError::SourceNotReady);
     };
 
     let mut message_hash_to_block_hash_map: BTreeMap<String, String> = BTreeMap::new();
 
-    // println!("TAG TO BLOCK HASH: {:#?}", tag_to_block_hash_map);
-
-    for publish_message in publish_messages {
-        // let block_hash = publish_message.events[0].hash.clone();
+    for (publish_message, publish_event) in publish_messages {
         let block_hash =
             if let Some(block_hash) = tag_to_block_hash_map.get(&publish_message.node_tag) {
                 block_hash.to_string()
@@ -206,51 +221,38 @@ pub fn aggregate_first_receive(
             by_block.insert(block_hash.clone(), publication_data);
         }
 
-        // let peer_id = publish_message.events[0].peer_id.as_ref().unwrap().clone();
-
-        message_hash_to_block_hash_map.insert(publish_message.events[0].hash.clone(), block_hash);
+        message_hash_to_block_hash_map.insert(publish_event.hash.clone(), block_hash);
     }
 
-    // println!(
-    //     "MESSAGE HASH TO BLOCK HASH: {:#?}",
-    //     message_hash_to_block_hash_map
-    // );
+    for captured in events.iter() {
+        for cpnp_event in captured.events.iter() {
+            // ignore other event types
+            if cpnp_event.r#type != CpnpEventType::ReceivedGossip {
+                continue;
+            }
 
-    for event in events.clone() {
-        let block_hash =
-            if let Some(block_hash) = message_hash_to_block_hash_map.get(&event.events[0].hash) {
-                block_hash.to_string()
+            let block_hash =
+                if let Some(block_hash) = message_hash_to_block_hash_map.get(&cpnp_event.hash) {
+                    block_hash.to_string()
+                } else {
+                    continue;
+                };
+            let block_data = if let Some(block_data) = by_block.get_mut(&block_hash) {
+                block_data
             } else {
-                // println!("Not found {}", event.events[0].hash);
-                // panic!();
                 continue;
             };
-        let block_data = if let Some(block_data) = by_block.get_mut(&block_hash) {
-            // println!("Found matching event with hash: {}", event.events[0].hash);
-            block_data
-        } else {
-            // println!("Ignoring event with hash: {}", event.events[0].hash);
-            // return Err(AggregatorError::SourceNotReady);
-            continue;
-        };
 
-        // ignore other event types
-        // TODO: enum...
-        if event.events[0].r#type == "received_gossip" {
-            let source_node = event.events[0]
-                .peer_id
-                .as_ref()
-                .unwrap_or(&String::default())
-                .to_string();
+            let source_node = cpnp_event.peer_id.clone().unwrap_or_default();
             let source_node_tag = peer_id_to_tag_map
                 .get(&source_node)
-                .unwrap_or(&"".to_string())
-                .to_string();
-            let current_node = event.node_address;
-            let current_node_tag = event.node_tag;
+                .cloned()
+                .unwrap_or_default();
+            let current_node = &captured.node_address;
+            let current_node_tag = captured.node_tag.clone();
 
             // skip if we already saw a message for this node with with this block
-            if block_data.node_latencies.get(&current_node_tag).is_some() {
+            if block_data.node_latencies.contains_key(&current_node_tag) {
                 continue;
             }
 
@@ -264,28 +266,25 @@ pub fn aggregate_first_receive(
 
             let node_data = CpnpLatencyAggregationData {
                 message_source: source_node_tag.to_string(),
-                message_source_tag: peer_id_to_tag_map
-                    .get(&source_node)
-                    .unwrap_or(&"".to_string())
-                    .to_string(),
+                message_source_tag: source_node_tag.to_string(),
                 node_address: current_node.ip(),
                 node_tag: current_node_tag.clone(),
-                receive_time: event.real_time_microseconds,
+                receive_time: captured.real_time_microseconds,
                 latency_since_sent: Some(
-                    event
+                    captured
                         .real_time_microseconds
                         .saturating_sub(source_receive_time),
                 ),
-                latency_since_block_publication: event
+                latency_since_block_publication: captured
                     .real_time_microseconds
                     .saturating_sub(block_data.publish_time),
                 latency_since_sent_seconds: Some(microseconds_u64_to_f64(
-                    event
+                    captured
                         .real_time_microseconds
                         .saturating_sub(source_receive_time),
                 )),
                 latency_since_block_publication_seconds: microseconds_u64_to_f64(
-                    event
+                    captured
                         .real_time_microseconds
                         .saturating_sub(block_data.publish_time),
                 ),
@@ -303,7 +302,7 @@ pub fn aggregate_first_receive(
             block_data.graph.update_edge(
                 source_graph_vertex,
                 *destination_graph_vertex,
-                event
+                captured
                     .real_time_microseconds
                     .saturating_sub(source_receive_time),
             );
@@ -320,11 +319,18 @@ pub fn aggregate_first_receive(
         let graph_count = connected_components(&block_data.graph);
         let is_graph_cyclic = is_cyclic_directed(&block_data.graph);
 
-        // let missing_nodes = nodes_in_cluster
-        //     .clone()
-        //     .into_iter()
-        //     .filter(|node| !block_data.unique_nodes.contains_key(node))
-        //     .collect();
+        let missing_nodes: Vec<String> = cluster_nodes
+            .iter()
+            .filter(|node| !block_data.node_latencies.contains_key(*node))
+            .cloned()
+            .collect();
+
+        let late_nodes: Vec<String> = block_data
+            .node_latencies
+            .values()
+            .filter(|data| data.latency_since_block_publication > receive_deadline_micros)
+            .map(|data| data.node_tag.clone())
+            .collect();
 
         let info = MessageGraphInfo {
             node_count,
@@ -332,20 +338,18 @@ pub fn aggregate_first_receive(
             source_nodes,
             graph_count,
             is_graph_cyclic,
-            // TODO
-            missing_nodes: vec![],
+            missing_node_count: missing_nodes.len(),
+            not_received_within_deadline_count: missing_nodes.len() + late_nodes.len(),
+            missing_nodes,
+            late_node_count: late_nodes.len(),
+            late_nodes,
+            propagation: analyze_propagation(&block_data.graph),
         };
 
         block_data.graph_info = Some(info);
     }
 
     Ok((height, by_block))
-    // for (hash, data) in by_block {
-    //     let dot = Dot::new(&data.graph);
-    //     let file_path = format!("output/{}", &hash[..5]);
-    //     let mut f = std::fs::File::create(file_path).unwrap();
-    //     f.write_all(format!("{}", dot).as_bytes()).unwrap();
-    // }
 
     // TODO: move to tests
     // SANITY CHECK: first receive time < first send time
@@ -379,17 +383,125 @@ fn get_source_nodes(graph: &MessageGraph) -> Vec<String> {
 }
 
 /// TODO: this is dangerous, validate the conversion!
-fn microseconds_u64_to_f64(micros: u64) -> f64 {
+pub fn microseconds_u64_to_f64(micros: u64) -> f64 {
     let micros = micros as f64;
     micros / 1_000_000.0
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use crate::debugger_data::{DebuggerCpnpResponse, NodeAddressCluster};
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::debugger_data::{CpnpEventType, CpnpMessageType, DebuggerCpnpResponse};
+
+    use super::{aggregate_first_receive, CpnpBlockPublication};
+
+    fn captured(node_tag: &str, time: u64, events: serde_json::Value) -> DebuggerCpnpResponse {
+        let raw = serde_json::json!([{
+            "time_microseconds": time,
+            "real_time_microseconds": time,
+            "node_address": format!("{node_tag}:8302"),
+            "events": events,
+        }]);
+        let mut data: DebuggerCpnpResponse = serde_json::from_value(raw).unwrap();
+        data[0].node_tag = node_tag.to_string();
+        data
+    }
+
+    #[test]
+    fn test_event_type_fallback() {
+        let event_type: CpnpEventType = serde_json::from_str("\"received_gossip\"").unwrap();
+        assert_eq!(CpnpEventType::ReceivedGossip, event_type);
+        let event_type: CpnpEventType = serde_json::from_str("\"graft\"").unwrap();
+        assert_eq!(CpnpEventType::Unknown("graft".to_string()), event_type);
+        let message_type: CpnpMessageType = serde_json::from_str("\"new_state\"").unwrap();
+        assert_eq!(CpnpMessageType::NewState, message_type);
+        let message_type: CpnpMessageType = serde_json::from_str("\"gossip_net\"").unwrap();
+        assert_eq!(
+            CpnpMessageType::Unknown("gossip_net".to_string()),
+            message_type
+        );
+    }
 
-//     fn test_data() -> (Vec<DebuggerCpnpResponse>, Vec<NodeAddressCluster>) {
+    #[test]
+    fn test_first_receive_handles_all_and_empty_events() {
+        // the pool diff published before the block is not a block publication
+        let publish = serde_json::json!([
+            {
+                "type": "publish_gossip",
+                "hash": "diff1",
+                "msg": { "type": "snark_pool_diff", "height": 0 }
+            },
+            {
+                "type": "publish_gossip",
+                "hash": "msg1",
+                "msg": { "type": "new_state", "height": 10 }
+            }
+        ]);
+        // the receive event is not the first one in the captured data
+        let receive = serde_json::json!([
+            {
+                "type": "subscribe",
+                "hash": "other",
+                "msg": { "type": "new_state", "height": 10 }
+            },
+            {
+                "type": "received_gossip",
+                "peer_id": "peer_prod1",
+                "hash": "msg1",
+                "msg": { "type": "new_state", "height": 10 }
+            }
+        ]);
+
+        let data = vec![
+            captured("prod1", 1_000_000, publish),
+            captured("node1", 1_500_000, receive),
+            captured("node2", 1_600_000, serde_json::json!([])),
+        ];
+
+        let peer_id_to_tag_map = BTreeMap::from([("peer_prod1".to_string(), "prod1".to_string())]);
+        let tag_to_block_hash_map = BTreeMap::from([("prod1".to_string(), "Block1".to_string())]);
+
+        let cluster_nodes = vec![
+            "prod1".to_string(),
+            "node1".to_string(),
+            "node2".to_string(),
+        ];
+
+        let (height, by_block) = aggregate_first_receive(
+            data,
+            &peer_id_to_tag_map,
+            &tag_to_block_hash_map,
+            &cluster_nodes,
+            400_000,
+        )
+        .unwrap();
+
+        assert_eq!(10, height);
+        let block_data = by_block.get("Block1").unwrap();
+        let node1 = block_data.node_latencies.get("node1").unwrap();
+        assert_eq!("prod1", node1.message_source_tag);
+        assert_eq!(500_000, node1.latency_since_block_publication);
+        assert!(!block_data.node_latencies.contains_key("node2"));
+
+        let graph_info = block_data.graph_info.as_ref().unwrap();
+        assert_eq!(vec!["node2".to_string()], graph_info.missing_nodes);
+        assert_eq!(vec!["node1".to_string()], graph_info.late_nodes);
+        assert_eq!(2, graph_info.not_received_within_deadline_count);
+        assert_eq!(Some(&1), graph_info.propagation.hop_counts.get("node1"));
+
+        // the graph survives a storage dump
+        let dumped = serde_json::to_string(block_data).unwrap();
+        let loaded: CpnpBlockPublication = serde_json::from_str(&dumped).unwrap();
+        assert_eq!(block_data.graph.node_count(), loaded.graph.node_count());
+        assert_eq!(1, loaded.graph.edge_count());
+        assert_eq!(Some(&500_000), loaded.graph.edge_weights().next());
+        assert_eq!(block_data.node_latencies.len(), loaded.node_latencies.len());
+    }
 
-//         (vec![], vec![])
-//     }
-// }
+    #[test]
+    fn test_first_receive_without_events() {
+        let data = vec![captured("node1", 1_000_000, serde_json::json!([]))];
+        assert!(aggregate_first_receive(data, &BTreeMap::new(), &BTreeMap::new(), &[], 0).is_err());
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::nodes::{BestChain, BestChainBlock, ConsensusState, ProtocolState};
+
+use super::BlockHash;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct BestChainConsensus {
+    pub queried_nodes: Vec<String>,
+    pub heights: BTreeMap<usize, HeightAgreement>,
+    /// Nodes whose tip is neither on the majority chain nor ahead of it
+    pub divergent_nodes: BTreeMap<String, NodeTip>,
+    /// Height and parent hash of every block reported by the nodes
+    #[serde(skip)]
+    blocks: BTreeMap<BlockHash, (usize, BlockHash)>,
+    /// The tip most nodes agree with, the majority chain ends in it
+    #[serde(skip)]
+    majority_tip: BlockHash,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct HeightAgreement {
+    pub majority_hash: BlockHash,
+    pub agreeing_node_count: usize,
+    pub node_count: usize,
+    pub agreement: f64,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct NodeTip {
+    pub height: usize,
+    pub state_hash: BlockHash,
+    /// The block of the majority chain at that height, or its tip when the node is ahead of it
+    pub majority_hash: BlockHash,
+}
+
+impl BestChainConsensus {
+    /// The chain ending in the tip most nodes agree with, following the parent hashes back
+    pub fn majority_chain(&self) -> BestChain {
+        let mut chain = vec![];
+        let mut state_hash = self.majority_tip.clone();
+
+        while let Some((height, previous_state_hash)) = self.blocks.get(&state_hash) {
+            chain.push(BestChainBlock {
+                state_hash,
+                protocol_state: ProtocolState {
+                    previous_state_hash: previous_state_hash.clone(),
+                    consensus_state: ConsensusState {
+                        block_height: height.to_string(),
+                    },
+                },
+            });
+            state_hash = previous_state_hash.clone();
+        }
+
+        chain.reverse();
+        chain
+    }
+}
+
+/// The hashes of the block and all its known ancestors
+fn ancestry(
+    blocks: &BTreeMap<BlockHash, (usize, BlockHash)>,
+    state_hash: &BlockHash,
+) -> BTreeSet<BlockHash> {
+    let mut ancestry = BTreeSet::new();
+    let mut state_hash = state_hash;
+    while let Some((_, previous_state_hash)) = blocks.get(state_hash) {
+        ancestry.insert(state_hash.clone());
+        state_hash = previous_state_hash;
+    }
+    ancestry
+}
+
+pub fn aggregate_best_chains(best_chains: BTreeMap<String, BestChain>) -> BestChainConsensus {
+    let mut votes: BTreeMap<usize, BTreeMap<BlockHash, usize>> = BTreeMap::new();
+    let mut tips: BTreeMap<String, (usize, BlockHash)> = BTreeMap::new();
+    let mut blocks: BTreeMap<BlockHash, (usize, BlockHash)> = BTreeMap::new();
+
+    for (tag, best_chain) in best_chains.iter() {
+        for block in best_chain {
+            let height = block
+                .protocol_state
+                .consensus_state
+                .block_height
+                .parse()
+                .unwrap_or_default();
+            *votes
+                .entry(height)
+                .or_default()
+                .entry(block.state_hash.clone())
+                .or_default() += 1;
+            blocks.insert(
+                block.state_hash.clone(),
+                (height, block.protocol_state.previous_state_hash.clone()),
+            );
+
+            if tips.get(tag).map(|(h, _)| height > *h).unwrap_or(true) {
+                tips.insert(tag.clone(), (height, block.state_hash.clone()));
+            }
+        }
+    }
+
+    let heights: BTreeMap<usize, HeightAgreement> = votes
+        .into_iter()
+        .filter_map(|(height, hashes)| {
+            let node_count: usize = hashes.values().sum();
+            // on a tie, pick the lowest hash so the result is deterministic
+            hashes
+                .into_iter()
+                .reduce(|best, current| if current.1 > best.1 { current } else { best })
+                .map(|(majority_hash, agreeing_node_count)| {
+                    (
+                        height,
+                        HeightAgreement {
+                            majority_hash,
+                            agreeing_node_count,
+                            node_count,
+                            agreement: agreeing_node_count as f64 / node_count as f64,
+                        },
+                    )
+                })
+        })
+        .collect();
+
+    // a node agrees with a tip when it is on the same chain, either lagging behind it or ahead of it
+    let ancestries: BTreeMap<&BlockHash, BTreeSet<BlockHash>> = tips
+        .values()
+        .map(|(_, state_hash)| (state_hash, ancestry(&blocks, state_hash)))
+        .collect();
+    let agrees = |tip: &BlockHash, node_tip: &BlockHash| {
+        ancestries[tip].contains(node_tip) || ancestries[node_tip].contains(tip)
+    };
+
+    // on a tie, prefer the higher tip, then the lowest hash so the result is deterministic
+    let majority_tip = tips
+        .values()
+        .map(|(height, state_hash)| {
+            let agreeing_node_count = tips
+                .values()
+                .filter(|(_, node_tip)| agrees(state_hash, node_tip))
+                .count();
+            (agreeing_node_count, *height, state_hash)
+        })
+        .reduce(|best, current| {
+            if (current.0, current.1) > (best.0, best.1)
+                || ((current.0, current.1) == (best.0, best.1) && current.2 < best.2)
+            {
+                current
+            } else {
+                best
+            }
+        })
+        .map(|(_, _, state_hash)| state_hash.clone())
+        .unwrap_or_default();
+
+    let majority_chain: BTreeMap<usize, &BlockHash> = ancestries
+        .get(&majority_tip)
+        .into_iter()
+        .flatten()
+        .map(|state_hash| (blocks[state_hash].0, state_hash))
+        .collect();
+
+    let divergent_nodes = tips
+        .iter()
+        .filter(|(_, (_, state_hash))| !agrees(&majority_tip, state_hash))
+        .map(|(tag, (height, state_hash))| {
+            let majority_hash = majority_chain
+                .range(..=height)
+                .next_back()
+                .map(|(_, majority_hash)| (*majority_hash).clone())
+                .unwrap_or_default();
+            (
+                tag.clone(),
+                NodeTip {
+                    height: *height,
+                    state_hash: state_hash.clone(),
+                    majority_hash,
+                },
+            )
+        })
+        .collect();
+
+    BestChainConsensus {
+        queried_nodes: best_chains.into_keys().collect(),
+        heights,
+        divergent_nodes,
+        blocks,
+        majority_tip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::nodes::{BestChainBlock, ConsensusState, ProtocolState};
+
+    use super::{aggregate_best_chains, BestChainConsensus};
+
+    fn chain(hashes: &[&str]) -> Vec<BestChainBlock> {
+        hashes
+            .iter()
+            .enumerate()
+            .map(|(i, hash)| BestChainBlock {
+                state_hash: hash.to_string(),
+                protocol_state: ProtocolState {
+                    previous_state_hash: i
+                        .checked_sub(1)
+                        .map(|previous| hashes[previous].to_string())
+                        .unwrap_or_default(),
+                    consensus_state: ConsensusState {
+                        block_height: (i + 1).to_string(),
+                    },
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_best_chain_majority_and_divergent_nodes() {
+        let mut best_chains = BTreeMap::new();
+        best_chains.insert("seed1".to_string(), chain(&["A1", "A2", "A3"]));
+        best_chains.insert("prod01".to_string(), chain(&["A1", "A2", "A3"]));
+        best_chains.insert("prod02".to_string(), chain(&["A1", "B2", "B3"]));
+        // lagging node, but on the majority chain
+        best_chains.insert("prod03".to_string(), chain(&["A1", "A2"]));
+
+        let consensus = aggregate_best_chains(best_chains);
+
+        assert_eq!(4, consensus.queried_nodes.len());
+
+        let height_3 = consensus.heights.get(&3).unwrap();
+        assert_eq!("A3", height_3.majority_hash);
+        assert_eq!(2, height_3.agreeing_node_count);
+        assert_eq!(3, height_3.node_count);
+
+        assert_eq!(1, consensus.divergent_nodes.len());
+        let divergent = consensus.divergent_nodes.get("prod02").unwrap();
+        assert_eq!("B3", divergent.state_hash);
+        assert_eq!("A3", divergent.majority_hash);
+
+        let majority_chain: Vec<String> = consensus
+            .majority_chain()
+            .into_iter()
+            .map(|b| b.state_hash)
+            .collect();
+        assert_eq!(vec!["A1", "A2", "A3"], majority_chain);
+    }
+
+    #[test]
+    fn test_majority_chain_ignores_a_node_ahead_on_a_minority_fork() {
+        let majority_chain = |consensus: &BestChainConsensus| -> Vec<String> {
+            consensus
+                .majority_chain()
+                .into_iter()
+                .map(|b| b.state_hash)
+                .collect()
+        };
+
+        let mut best_chains = BTreeMap::new();
+        best_chains.insert("prod01".to_string(), chain(&["A1", "A2"]));
+        best_chains.insert("prod02".to_string(), chain(&["A1", "A2"]));
+        best_chains.insert("prod03".to_string(), chain(&["A1", "A2"]));
+        // the only node that reached height 3, but on a fork
+        best_chains.insert("prod2".to_string(), chain(&["A1", "B2", "B3"]));
+
+        let consensus = aggregate_best_chains(best_chains.clone());
+        assert_eq!(vec!["A1", "A2"], majority_chain(&consensus));
+        assert_eq!(1, consensus.divergent_nodes.len());
+        let divergent = consensus.divergent_nodes.get("prod2").unwrap();
+        assert_eq!("B3", divergent.state_hash);
+        assert_eq!("A2", divergent.majority_hash);
+
+        // the others catch up, the chain is only extended
+        best_chains.insert("prod01".to_string(), chain(&["A1", "A2", "A3"]));
+        best_chains.insert("prod02".to_string(), chain(&["A1", "A2", "A3"]));
+        let consensus = aggregate_best_chains(best_chains);
+        assert_eq!(vec!["A1", "A2", "A3"], majority_chain(&consensus));
+        assert_eq!(
+            vec!["prod2"],
+            consensus.divergent_nodes.keys().collect::<Vec<_>>()
+        );
+        assert_eq!("A3", consensus.divergent_nodes["prod2"].majority_hash);
+    }
+}
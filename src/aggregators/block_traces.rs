@@ -4,13 +4,15 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     nodes::{
-        BlockStructuredTrace, DaemonMetrics, DaemonStatusDataSlim, GlobalSlot, TraceSource,
-        TraceStatus,
+        BlockStructuredTrace, DaemonMetrics, DaemonStatusDataSlim, DiscardedCommands, GlobalSlot,
+        TraceSource, TraceStatus,
     },
-    storage::{BlockSummary, PeerTiming},
+    storage::{upper_outlier_bound, BlockSummary, PeerTiming},
     AggregatorResult,
 };
 
+use super::{clock_offset, ClockSkews};
+
 type BlockHash = String;
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
@@ -52,6 +54,10 @@ impl AggregatedBlockTraces {
             .unwrap_or_default()
     }
 
+    pub fn traces(&self) -> impl Iterator<Item = &BlockTraceAggregatorReport> {
+        self.inner.values().flatten()
+    }
+
     // TODO: remove this ideally
     pub fn inner(&self) -> BTreeMap<String, Vec<BlockTraceAggregatorReport>> {
         self.inner.clone()
@@ -60,6 +66,14 @@ impl AggregatedBlockTraces {
     pub fn block_summaries(&self, height: usize) -> BTreeMap<BlockHash, BlockSummary> {
         let mut block_summaries = BTreeMap::new();
 
+        // the peers of a node are all the nodes on the height, the times are selected like in the build summary
+        let application_bound = upper_outlier_bound(&self.application_times());
+        let receive_latency_bound = upper_outlier_bound(&self.receive_latencies());
+        let is_outlier = |value: Option<f64>, bound: Option<f64>| match (value, bound) {
+            (Some(value), Some(bound)) => value > bound,
+            _ => false,
+        };
+
         for (block_hash, block_traces_per_node) in self.inner.iter() {
             let global_slot = block_traces_per_node
                 .iter()
@@ -71,6 +85,14 @@ impl AggregatedBlockTraces {
             let block_producer = block_traces_per_node
                 .iter()
                 .find_map(|t| t.block_producer.clone());
+            let proof_count = block_traces_per_node.iter().find_map(|t| t.proof_count);
+            let winner = block_traces_per_node.iter().find_map(|t| t.winner.clone());
+            let coinbase_receiver = block_traces_per_node
+                .iter()
+                .find_map(|t| t.coinbase_receiver.clone());
+            let discarded_commands = block_traces_per_node
+                .iter()
+                .find_map(|t| t.discarded_commands.clone());
             let block_producer_nodes = block_traces_per_node
                 .iter()
                 .filter(|t| t.is_producer)
@@ -78,27 +100,52 @@ impl AggregatedBlockTraces {
                 .collect();
             let max_receive_latency = block_traces_per_node
                 .iter()
-                .filter(|t| !t.is_producer)
+                .filter(|t| !t.is_producer && t.is_gossip_receive())
                 .filter_map(|t| t.receive_latency)
                 .reduce(|a, b| a.max(b))
                 .unwrap_or_default();
             let peer_timings = block_traces_per_node
                 .iter()
-                .map(|t| PeerTiming {
-                    node: t.node.clone(),
-                    block_processing_time: t.block_application,
-                    receive_latency: t.receive_latency,
+                .filter(|t| t.is_gossip_receive())
+                .map(|t| {
+                    let mut timing = PeerTiming::from(t);
+                    if !t.is_producer {
+                        timing.application_outlier =
+                            is_outlier(t.block_application, application_bound);
+                        timing.receive_latency_outlier = t.is_gossip_receive()
+                            && is_outlier(t.receive_latency, receive_latency_bound);
+                    }
+                    timing
                 })
                 .collect();
+            let catchup_timings: Vec<PeerTiming> = block_traces_per_node
+                .iter()
+                .filter(|t| matches!(t.source, Some(TraceSource::Catchup)))
+                .map(PeerTiming::from)
+                .collect();
+            let reconstruct_timings: Vec<PeerTiming> = block_traces_per_node
+                .iter()
+                .filter(|t| matches!(t.source, Some(TraceSource::Reconstruct)))
+                .map(PeerTiming::from)
+                .collect();
             let summary = BlockSummary {
                 block_hash: block_hash.clone(),
                 global_slot,
                 tx_count,
                 date_time,
                 block_producer,
+                proof_count,
+                winner,
+                coinbase_receiver,
+                discarded_commands,
                 block_producer_nodes,
+                canonical: None,
                 max_receive_latency,
                 peer_timings,
+                catchup_count: catchup_timings.len(),
+                catchup_timings,
+                reconstruct_count: reconstruct_timings.len(),
+                reconstruct_timings,
                 height,
             };
             block_summaries.insert(block_hash.to_string(), summary);
@@ -139,6 +186,22 @@ impl AggregatedBlockTraces {
             .collect()
     }
 
+    pub fn discarded_commands_per_block(&self) -> Vec<(BlockHash, DiscardedCommands)> {
+        self.inner
+            .values()
+            .flat_map(|traces| {
+                traces
+                    .iter()
+                    .filter(|val| val.is_producer)
+                    .filter_map(|val| {
+                        val.discarded_commands
+                            .clone()
+                            .map(|discarded| (val.block_hash.to_string(), discarded))
+                    })
+            })
+            .collect()
+    }
+
     pub fn application_times(&self) -> Vec<f64> {
         self.inner
             .values()
@@ -171,7 +234,7 @@ impl AggregatedBlockTraces {
             .flat_map(|traces| {
                 traces
                     .iter()
-                    .filter(|val| !val.is_producer)
+                    .filter(|val| !val.is_producer && val.is_gossip_receive())
                     .filter_map(|val| val.receive_latency)
                     .collect::<Vec<f64>>()
             })
@@ -216,6 +279,24 @@ pub struct BlockTraceAggregatorReport {
     pub global_slot: Option<GlobalSlot>,
     #[serde(skip)]
     pub included_tranasction_count: Option<usize>,
+    #[serde(default)]
+    pub proof_count: Option<usize>,
+    #[serde(default)]
+    pub winner: Option<String>,
+    #[serde(default)]
+    pub coinbase_receiver: Option<String>,
+    #[serde(default)]
+    pub discarded_commands: Option<DiscardedCommands>,
+}
+
+impl BlockTraceAggregatorReport {
+    /// Catchup and reconstructed blocks were not received through gossip, so their latency is not a propagation metric
+    pub fn is_gossip_receive(&self) -> bool {
+        !matches!(
+            self.source,
+            Some(TraceSource::Catchup) | Some(TraceSource::Reconstruct)
+        )
+    }
 }
 
 pub fn aggregate_block_traces(
@@ -223,6 +304,7 @@ pub fn aggregate_block_traces(
     state_hash: &str,
     node_infos: &BTreeMap<String, DaemonStatusDataSlim>,
     traces: BTreeMap<String, BlockStructuredTrace>,
+    clock_skews: Option<&ClockSkews>,
 ) -> AggregatorResult<Vec<BlockTraceAggregatorReport>> {
     // let mut external_receivers: Vec<(String, BlockStructuredTrace)> = traces.clone().into_iter()
     //     .filter(|(_, trace)| matches!(trace.source, TraceSource::External) || matches!(trace.source, TraceSource::Catchup))
@@ -257,7 +339,7 @@ pub fn aggregate_block_traces(
 
     // TODO: Should be the timestamp the first producer finished?
     // TraceSource::Internal -> sort by ~sent_time=(started_at + total_time)
-    let first_received = internal_receivers.get(0);
+    let first_received = internal_receivers.first();
 
     let report: Vec<BlockTraceAggregatorReport> = node_infos
         .iter()
@@ -278,6 +360,16 @@ pub fn aggregate_block_traces(
                 global_slot: trace.and_then(|t| t.metadata.global_slot.clone()),
                 block_producer: trace.and_then(|t| t.metadata.creator.clone()),
                 included_tranasction_count: tx_count,
+                proof_count: trace.and_then(|t| t.metadata.proof_count),
+                winner: trace.and_then(|t| t.metadata.winner.clone()),
+                coinbase_receiver: trace.and_then(|t| t.metadata.coinbase_receiver.clone()),
+                discarded_commands: trace.and_then(|t| {
+                    t.metadata
+                        .diff_log
+                        .as_ref()
+                        .and_then(|diff_logs| diff_logs.last())
+                        .map(|d| d.discarded_commands.clone())
+                }),
                 height,
                 node: node.to_string(),
                 node_address: node_info.daemon_status.addrs_and_ports.external_ip.clone(),
@@ -287,11 +379,13 @@ pub fn aggregate_block_traces(
                 transaction_pool_size: node_info.daemon_status.metrics.transaction_pool_size,
                 metrics: node_info.daemon_status.metrics.clone(),
                 date_time: trace.map(|t| t.sections[0].checkpoints[0].started_at),
+                // the timestamps come from different clocks, so correct them with the estimated skews if provided
                 receive_latency: trace.and_then(|t| {
-                    first_received.map(|first_received| {
-                        t.sections[0].checkpoints[0].started_at
-                            - (first_received.1.sections[0].checkpoints[0].started_at
-                                + first_received.1.total_time)
+                    first_received.map(|(first_received_node, first_received)| {
+                        (t.sections[0].checkpoints[0].started_at - clock_offset(clock_skews, node))
+                            - (first_received.sections[0].checkpoints[0].started_at
+                                + first_received.total_time
+                                - clock_offset(clock_skews, first_received_node))
                     })
                 }),
                 block_application: trace
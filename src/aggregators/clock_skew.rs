@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cross_validation::ValidationReport, storage::median};
+
+use super::CpnpBlockPublication;
+
+/// Clock skew estimates keyed by node tag
+pub type ClockSkews = BTreeMap<String, ClockSkewEstimate>;
+
+const GAUSS_SEIDEL_ITERATIONS: usize = 100;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ClockSkewEstimate {
+    /// Estimated offset of the node clock from the cluster in seconds, positive means the node clock is ahead
+    pub offset: f64,
+    /// Offset of the debugger clock, estimated from gossip exchanged in both directions with other nodes
+    pub gossip_offset: Option<f64>,
+    pub gossip_pair_count: usize,
+    /// Offset of the trace clock relative to the debugger clock, compared to the rest of the cluster
+    pub cross_validation_offset: Option<f64>,
+    pub cross_validation_sample_count: usize,
+}
+
+/// The offset to subtract from a timestamp taken on the node, 0 when the node has no estimate
+pub fn clock_offset(clock_skews: Option<&ClockSkews>, node: &str) -> f64 {
+    clock_skews
+        .and_then(|skews| skews.get(node))
+        .map(|estimate| estimate.offset)
+        .unwrap_or_default()
+}
+
+pub fn estimate_clock_skews<'a>(
+    publications: impl Iterator<Item = &'a CpnpBlockPublication>,
+    validations: impl Iterator<Item = &'a ValidationReport>,
+) -> ClockSkews {
+    let mut skews = ClockSkews::new();
+
+    for (node, (offset, pair_count)) in gossip_offsets(publications) {
+        let estimate = skews.entry(node).or_default();
+        estimate.gossip_offset = Some(offset);
+        estimate.gossip_pair_count = pair_count;
+    }
+
+    for (node, (offset, sample_count)) in cross_validation_offsets(validations) {
+        let estimate = skews.entry(node).or_default();
+        estimate.cross_validation_offset = Some(offset);
+        estimate.cross_validation_sample_count = sample_count;
+    }
+
+    for estimate in skews.values_mut() {
+        estimate.offset = estimate.gossip_offset.unwrap_or_default()
+            + estimate.cross_validation_offset.unwrap_or_default();
+    }
+
+    skews
+}
+
+/// NTP like estimation: the minimal delay observed from A to B is `latency + (o_B - o_A)`, the one from B to A is
+/// `latency + (o_A - o_B)`, so assuming symmetric latency their half difference is the relative offset of the pair.
+/// The per node offsets are then fitted to the pairwise ones with least squares, centered around 0.
+fn gossip_offsets<'a>(
+    publications: impl Iterator<Item = &'a CpnpBlockPublication>,
+) -> BTreeMap<String, (f64, usize)> {
+    let mut min_delays: BTreeMap<(String, String), i64> = BTreeMap::new();
+
+    for publication in publications {
+        for data in publication.node_latencies.values() {
+            if data.message_source_tag.is_empty() || data.message_source_tag == data.node_tag {
+                continue;
+            }
+            let source_receive_time =
+                if let Some(source) = publication.node_latencies.get(&data.message_source_tag) {
+                    source.receive_time
+                } else {
+                    continue;
+                };
+            let delay = data.receive_time as i64 - source_receive_time as i64;
+            min_delays
+                .entry((data.message_source_tag.clone(), data.node_tag.clone()))
+                .and_modify(|min| *min = (*min).min(delay))
+                .or_insert(delay);
+        }
+    }
+
+    // relative offsets (o_to - o_from) in seconds, for the pairs seen in both directions
+    let mut neighbors: BTreeMap<String, Vec<(String, f64)>> = BTreeMap::new();
+    for ((from, to), delay) in min_delays.iter() {
+        if from >= to {
+            continue;
+        }
+        if let Some(reverse_delay) = min_delays.get(&(to.clone(), from.clone())) {
+            let relative = (delay - reverse_delay) as f64 / 2.0 / 1_000_000.0;
+            neighbors
+                .entry(to.clone())
+                .or_default()
+                .push((from.clone(), relative));
+            neighbors
+                .entry(from.clone())
+                .or_default()
+                .push((to.clone(), -relative));
+        }
+    }
+
+    let mut offsets: BTreeMap<String, f64> = neighbors.keys().map(|n| (n.clone(), 0.0)).collect();
+    for _ in 0..GAUSS_SEIDEL_ITERATIONS {
+        for (node, node_neighbors) in neighbors.iter() {
+            let sum: f64 = node_neighbors
+                .iter()
+                .map(|(neighbor, relative)| offsets[neighbor] + relative)
+                .sum();
+            offsets.insert(node.clone(), sum / node_neighbors.len() as f64);
+        }
+    }
+
+    let mean = offsets.values().sum::<f64>() / offsets.len().max(1) as f64;
+    offsets
+        .into_iter()
+        .map(|(node, offset)| {
+            let pair_count = neighbors[&node].len();
+            (node, (offset - mean, pair_count))
+        })
+        .collect()
+}
+
+/// The trace and debugger timestamps of a block receive differ by the processing delay, which is about the same
+/// on every node, so the deviation of a node from the cluster median is attributed to its trace clock
+fn cross_validation_offsets<'a>(
+    validations: impl Iterator<Item = &'a ValidationReport>,
+) -> BTreeMap<String, (f64, usize)> {
+    let mut deltas: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for report in validations {
+        for (node, delta) in report.received_time_comparison.iter() {
+            deltas.entry(node.clone()).or_default().push(*delta);
+        }
+    }
+
+    let node_medians: BTreeMap<String, (f64, usize)> = deltas
+        .into_iter()
+        .map(|(node, mut node_deltas)| {
+            let count = node_deltas.len();
+            (node, (median(&mut node_deltas), count))
+        })
+        .collect();
+
+    let cluster_median = median(&mut node_medians.values().map(|(m, _)| *m).collect::<Vec<_>>());
+
+    node_medians
+        .into_iter()
+        .map(|(node, (node_median, count))| (node, (node_median - cluster_median, count)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        aggregators::{CpnpBlockPublication, CpnpLatencyAggregationData},
+        cross_validation::ValidationReport,
+    };
+
+    use super::estimate_clock_skews;
+
+    fn receive(
+        node: &str,
+        source: &str,
+        receive_time: u64,
+    ) -> (String, CpnpLatencyAggregationData) {
+        (
+            node.to_string(),
+            CpnpLatencyAggregationData {
+                node_tag: node.to_string(),
+                message_source_tag: source.to_string(),
+                receive_time,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn publication(receives: Vec<(String, CpnpLatencyAggregationData)>) -> CpnpBlockPublication {
+        let mut publication = CpnpBlockPublication::default();
+        publication.node_latencies = receives.into_iter().collect();
+        publication
+    }
+
+    #[test]
+    fn test_clock_skew_estimation() {
+        // node2 clock is 0.2s ahead of node1, the latency is 0.1s both ways
+        let publications = [
+            publication(vec![
+                receive("node1", "node1", 1_000_000),
+                receive("node2", "node1", 1_300_000),
+            ]),
+            publication(vec![
+                receive("node2", "node2", 2_200_000),
+                receive("node1", "node2", 2_100_000),
+            ]),
+        ];
+
+        let validations: Vec<ValidationReport> = [0.05, 0.06]
+            .into_iter()
+            .map(|node3_delta| ValidationReport {
+                received_time_comparison: BTreeMap::from([
+                    ("node1".to_string(), 0.01),
+                    ("node2".to_string(), 0.01),
+                    ("node3".to_string(), node3_delta),
+                ]),
+                ..Default::default()
+            })
+            .collect();
+
+        let skews = estimate_clock_skews(publications.iter(), validations.iter());
+
+        let node1 = skews.get("node1").unwrap();
+        let node2 = skews.get("node2").unwrap();
+        assert!((node1.gossip_offset.unwrap() + 0.1).abs() < 1e-9);
+        assert!((node2.gossip_offset.unwrap() - 0.1).abs() < 1e-9);
+        assert_eq!(1, node2.gossip_pair_count);
+        assert!((node2.offset - 0.1).abs() < 1e-9);
+
+        let node3 = skews.get("node3").unwrap();
+        assert_eq!(None, node3.gossip_offset);
+        assert_eq!(2, node3.cross_validation_sample_count);
+        assert!((node3.offset - 0.045).abs() < 1e-9);
+    }
+}
@@ -3,3 +3,12 @@ pub use aggregator_ipc::*;
 
 pub mod block_traces;
 pub use block_traces::*;
+
+pub mod best_chain_consensus;
+pub use best_chain_consensus::*;
+
+pub mod propagation_graph;
+pub use propagation_graph::*;
+
+pub mod clock_skew;
+pub use clock_skew::*;
@@ -0,0 +1,317 @@
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, VecDeque};
+
+use petgraph::algo::toposort;
+use petgraph::dot::Dot;
+use petgraph::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{microseconds_u64_to_f64, MessageGraph};
+
+/// Serializable form of the [MessageGraph], the edge weights are the latencies in microseconds
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PropagationGraph {
+    pub nodes: Vec<String>,
+    pub edges: Vec<PropagationEdge>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PropagationEdge {
+    pub source: String,
+    pub target: String,
+    pub latency: u64,
+    pub latency_seconds: f64,
+}
+
+impl From<&MessageGraph> for PropagationGraph {
+    fn from(graph: &MessageGraph) -> Self {
+        let nodes = graph.node_weights().cloned().collect();
+        let edges = graph
+            .edge_references()
+            .map(|edge| PropagationEdge {
+                source: graph[edge.source()].clone(),
+                target: graph[edge.target()].clone(),
+                latency: *edge.weight(),
+                latency_seconds: microseconds_u64_to_f64(*edge.weight()),
+            })
+            .collect();
+
+        Self { nodes, edges }
+    }
+}
+
+impl From<&PropagationGraph> for MessageGraph {
+    fn from(value: &PropagationGraph) -> Self {
+        let mut graph = MessageGraph::new();
+        let mut indices = HashMap::new();
+
+        for node in value.nodes.iter() {
+            indices.insert(node.clone(), graph.add_node(node.clone()));
+        }
+
+        for edge in value.edges.iter() {
+            let source = *indices
+                .entry(edge.source.clone())
+                .or_insert_with(|| graph.add_node(edge.source.clone()));
+            let target = *indices
+                .entry(edge.target.clone())
+                .or_insert_with(|| graph.add_node(edge.target.clone()));
+            graph.update_edge(source, target, edge.latency);
+        }
+
+        graph
+    }
+}
+
+/// Renders the graph in the graphviz DOT format, with the latencies (microseconds) as edge labels
+pub fn to_dot(graph: &MessageGraph) -> String {
+    format!("{}", Dot::new(graph))
+}
+
+pub fn serialize_graph<S: Serializer>(
+    graph: &MessageGraph,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    PropagationGraph::from(graph).serialize(serializer)
+}
+
+pub fn deserialize_graph<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<MessageGraph, D::Error> {
+    PropagationGraph::deserialize(deserializer).map(|graph| MessageGraph::from(&graph))
+}
+
+/// An edge is a slow relay when its latency is this many times over the median edge latency of the block
+const SLOW_EDGE_MEDIAN_FACTOR: u64 = 3;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PropagationAnalytics {
+    /// Hops needed to reach each node from the closest source (producer)
+    pub hop_counts: BTreeMap<String, usize>,
+    pub max_hop_count: usize,
+    /// The path from a source with the highest accumulated latency
+    pub critical_path: Vec<String>,
+    /// Accumulated latency of the critical path in microseconds
+    pub critical_path_latency: u64,
+    /// Number of nodes each node relayed the block to
+    pub fan_out: BTreeMap<String, usize>,
+    pub slow_edges: Vec<PropagationEdge>,
+}
+
+pub fn analyze_propagation(graph: &MessageGraph) -> PropagationAnalytics {
+    let sources: Vec<NodeIndex> = graph.externals(Incoming).collect();
+
+    // BFS from all the sources at once, so every node gets the hop count from the closest one
+    let mut hops: HashMap<NodeIndex, usize> = HashMap::new();
+    let mut queue: VecDeque<NodeIndex> = VecDeque::new();
+    for source in sources.iter() {
+        hops.insert(*source, 0);
+        queue.push_back(*source);
+    }
+    while let Some(node) = queue.pop_front() {
+        let hop = hops[&node];
+        for neighbor in graph.neighbors_directed(node, Outgoing) {
+            if let Entry::Vacant(entry) = hops.entry(neighbor) {
+                entry.insert(hop + 1);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    // longest path, relaxing the edges in topological order so every parent of a node is settled before it.
+    // The first receives can't form a cycle, but if the timestamps were off and they do, there is no critical path
+    let mut critical_path: Vec<NodeIndex> = vec![];
+    let mut critical_path_latency = 0;
+    if let Ok(order) = toposort(graph, None) {
+        let mut longest: HashMap<NodeIndex, (u64, Option<NodeIndex>)> =
+            sources.iter().map(|source| (*source, (0, None))).collect();
+        for node in order.iter() {
+            let latency = if let Some((latency, _)) = longest.get(node) {
+                *latency
+            } else {
+                continue;
+            };
+            for edge in graph.edges_directed(*node, Outgoing) {
+                let candidate = latency + edge.weight();
+                match longest.entry(edge.target()) {
+                    Entry::Occupied(mut entry) if entry.get().0 < candidate => {
+                        entry.insert((candidate, Some(*node)));
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert((candidate, Some(*node)));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut end = None;
+        for node in order.iter() {
+            if let Some((latency, _)) = longest.get(node) {
+                if *latency > critical_path_latency || end.is_none() {
+                    critical_path_latency = *latency;
+                    end = Some(*node);
+                }
+            }
+        }
+        while let Some(node) = end {
+            critical_path.push(node);
+            end = longest[&node].1;
+        }
+        critical_path.reverse();
+    }
+
+    let fan_out = graph
+        .node_indices()
+        .map(|node| {
+            (
+                graph[node].clone(),
+                graph.edges_directed(node, Outgoing).count(),
+            )
+        })
+        .collect();
+
+    let mut latencies: Vec<u64> = graph.edge_weights().copied().collect();
+    latencies.sort_unstable();
+    let median = latencies
+        .get(latencies.len() / 2)
+        .copied()
+        .unwrap_or_default();
+    let slow_edges = PropagationGraph::from(graph)
+        .edges
+        .into_iter()
+        .filter(|edge| median > 0 && edge.latency > median * SLOW_EDGE_MEDIAN_FACTOR)
+        .collect();
+
+    let hop_counts: BTreeMap<String, usize> = hops
+        .into_iter()
+        .map(|(node, hop)| (graph[node].clone(), hop))
+        .collect();
+
+    PropagationAnalytics {
+        max_hop_count: hop_counts.values().copied().max().unwrap_or_default(),
+        hop_counts,
+        critical_path: critical_path
+            .into_iter()
+            .map(|node| graph[node].clone())
+            .collect(),
+        critical_path_latency,
+        fan_out,
+        slow_edges,
+    }
+}
+
+/// Propagation analytics aggregated over all the blocks of a build
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PropagationSummary {
+    pub analyzed_block_count: usize,
+    pub max_hop_count: usize,
+    pub avg_max_hop_count: f64,
+    /// Critical path latencies in seconds
+    pub max_critical_path_latency: f64,
+    pub avg_critical_path_latency: f64,
+    pub slow_edge_count: usize,
+    /// Number of slow relay edges originating from each node
+    pub slow_relays: BTreeMap<String, usize>,
+}
+
+pub fn summarize_propagation<'a>(
+    analytics: impl Iterator<Item = &'a PropagationAnalytics>,
+) -> PropagationSummary {
+    let mut summary = PropagationSummary::default();
+    let mut hop_count_total = 0;
+    let mut critical_path_latency_total = 0;
+
+    for block_analytics in analytics {
+        summary.analyzed_block_count += 1;
+        summary.max_hop_count = summary.max_hop_count.max(block_analytics.max_hop_count);
+        hop_count_total += block_analytics.max_hop_count;
+        critical_path_latency_total += block_analytics.critical_path_latency;
+        summary.max_critical_path_latency =
+            summary
+                .max_critical_path_latency
+                .max(microseconds_u64_to_f64(
+                    block_analytics.critical_path_latency,
+                ));
+        summary.slow_edge_count += block_analytics.slow_edges.len();
+        for edge in block_analytics.slow_edges.iter() {
+            *summary.slow_relays.entry(edge.source.clone()).or_default() += 1;
+        }
+    }
+
+    if summary.analyzed_block_count > 0 {
+        summary.avg_max_hop_count = hop_count_total as f64 / summary.analyzed_block_count as f64;
+        summary.avg_critical_path_latency = microseconds_u64_to_f64(critical_path_latency_total)
+            / summary.analyzed_block_count as f64;
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze_propagation() {
+        // prod -> a -> b -> d
+        //      \-> c
+        let mut graph = MessageGraph::new();
+        let prod = graph.add_node("prod".to_string());
+        let a = graph.add_node("a".to_string());
+        let b = graph.add_node("b".to_string());
+        let c = graph.add_node("c".to_string());
+        let d = graph.add_node("d".to_string());
+        graph.update_edge(prod, a, 100);
+        graph.update_edge(a, b, 100);
+        graph.update_edge(b, d, 1_000);
+        graph.update_edge(prod, c, 150);
+
+        let analytics = analyze_propagation(&graph);
+
+        assert_eq!(3, analytics.max_hop_count);
+        assert_eq!(Some(&0), analytics.hop_counts.get("prod"));
+        assert_eq!(Some(&1), analytics.hop_counts.get("c"));
+        assert_eq!(Some(&3), analytics.hop_counts.get("d"));
+
+        assert_eq!(vec!["prod", "a", "b", "d"], analytics.critical_path);
+        assert_eq!(1_200, analytics.critical_path_latency);
+
+        assert_eq!(Some(&2), analytics.fan_out.get("prod"));
+        assert_eq!(Some(&0), analytics.fan_out.get("d"));
+
+        assert_eq!(1, analytics.slow_edges.len());
+        assert_eq!("b", analytics.slow_edges[0].source);
+        assert_eq!("d", analytics.slow_edges[0].target);
+
+        let summary =
+            summarize_propagation([&analytics, &PropagationAnalytics::default()].into_iter());
+        assert_eq!(2, summary.analyzed_block_count);
+        assert_eq!(3, summary.max_hop_count);
+        assert_eq!(1.5, summary.avg_max_hop_count);
+        assert_eq!(Some(&1), summary.slow_relays.get("b"));
+    }
+
+    #[test]
+    fn test_critical_path_diamond() {
+        // prod -> a -> d -> e
+        //      \-> b -/
+        // a is found first, but the path through b is the longer one
+        let mut graph = MessageGraph::new();
+        let prod = graph.add_node("prod".to_string());
+        let a = graph.add_node("a".to_string());
+        let b = graph.add_node("b".to_string());
+        let d = graph.add_node("d".to_string());
+        let e = graph.add_node("e".to_string());
+        graph.update_edge(prod, a, 100);
+        graph.update_edge(prod, b, 300);
+        graph.update_edge(a, d, 100);
+        graph.update_edge(b, d, 200);
+        graph.update_edge(d, e, 50);
+
+        let analytics = analyze_propagation(&graph);
+
+        assert_eq!(vec!["prod", "b", "d", "e"], analytics.critical_path);
+        assert_eq!(550, analytics.critical_path_latency);
+        assert_eq!(Some(&3), analytics.hop_counts.get("e"));
+    }
+}
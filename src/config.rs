@@ -11,6 +11,7 @@ const REMOTE_STORAGE_URL: &str = "ci.openmina.com:22";
 const REMOTE_STORAGE_PATH: &str = "/home/aggregator/storage.json";
 
 const DATA_PULL_INTERVAL_DEFAULT: u64 = 10;
+const RECEIVE_DEADLINE_DEFAULT: u64 = 10;
 
 #[derive(Clone, Debug)]
 pub struct AggregatorEnvironment {
@@ -21,6 +22,8 @@ pub struct AggregatorEnvironment {
     pub transaction_generator_node_count: usize,
     pub libp2p_ipc_encpoint: String,
     pub data_pull_interval: Duration,
+    pub debugger_pull_interval: Duration,
+    pub receive_deadline: Duration,
     pub rpc_port: u16,
     pub cluster_base_url: String,
     pub ci_api_url: String,
@@ -31,6 +34,10 @@ pub struct AggregatorEnvironment {
     pub remote_storage_path: String,
     pub use_internal_endpoints: bool,
     pub disable_aggregation: bool,
+    pub disable_debugger: bool,
+    pub apply_clock_skew_correction: bool,
+    /// Token the mutating RPC endpoints require as a bearer token, they are disabled without it
+    pub rpc_api_token: Option<String>,
 }
 
 impl Display for AggregatorEnvironment {
@@ -44,6 +51,12 @@ impl Display for AggregatorEnvironment {
             "\tdata_pull_interval: {}",
             self.data_pull_interval.as_secs()
         )?;
+        writeln!(
+            f,
+            "\tdebugger_pull_interval: {}",
+            self.debugger_pull_interval.as_secs()
+        )?;
+        writeln!(f, "\treceive_deadline: {}", self.receive_deadline.as_secs())?;
         writeln!(f, "\trpc_port: {}", self.rpc_port)?;
         writeln!(f, "\tcluster_base_url: {}", self.cluster_base_url)?;
         writeln!(f, "\tci_api_url: {}", self.ci_api_url)?;
@@ -54,7 +67,22 @@ impl Display for AggregatorEnvironment {
             "\tuse_internal_endpoints: {}",
             self.use_internal_endpoints
         )?;
-        writeln!(f, "\tdisable_aggregation: {}", self.disable_aggregation)
+        writeln!(f, "\tdisable_aggregation: {}", self.disable_aggregation)?;
+        writeln!(f, "\tdisable_debugger: {}", self.disable_debugger)?;
+        writeln!(
+            f,
+            "\tapply_clock_skew_correction: {}",
+            self.apply_clock_skew_correction
+        )?;
+        writeln!(
+            f,
+            "\trpc_api_token: {}",
+            if self.rpc_api_token.is_some() {
+                "set"
+            } else {
+                "not set"
+            }
+        )
     }
 }
 
@@ -103,6 +131,20 @@ pub fn set_environment() -> AggregatorEnvironment {
         .expect("DATA_PULL_INTERVAL should be a positve number representing seconds");
     let data_pull_interval = Duration::from_secs(data_pull_interval);
 
+    let debugger_pull_interval = env::var("DEBUGGER_PULL_INTERVAL")
+        .map(|v| {
+            v.parse::<u64>()
+                .expect("DEBUGGER_PULL_INTERVAL should be a positve number representing seconds")
+        })
+        .map(Duration::from_secs)
+        .unwrap_or(data_pull_interval);
+
+    let receive_deadline = env::var("RECEIVE_DEADLINE")
+        .unwrap_or_else(|_| RECEIVE_DEADLINE_DEFAULT.to_string())
+        .parse::<u64>()
+        .expect("RECEIVE_DEADLINE should be a positve number representing seconds");
+    let receive_deadline = Duration::from_secs(receive_deadline);
+
     let rpc_port = env::var("RPC_PORT")
         .unwrap_or_else(|_| RPC_PORT_DEFAULT.to_string())
         .parse::<u16>()
@@ -125,6 +167,12 @@ pub fn set_environment() -> AggregatorEnvironment {
 
     let use_internal_endpoints = env::var("USE_INTERNAL_ENDPOINTS").is_ok();
     let disable_aggregation = env::var("DISABLE_AGGREGATION").is_ok();
+    let disable_debugger = env::var("DISABLE_DEBUGGER").is_ok();
+    let apply_clock_skew_correction = env::var("APPLY_CLOCK_SKEW_CORRECTION").is_ok();
+    // an empty token would let an empty bearer token through, treat it as unset
+    let rpc_api_token = env::var("RPC_API_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty());
 
     AggregatorEnvironment {
         plain_node_count,
@@ -134,6 +182,8 @@ pub fn set_environment() -> AggregatorEnvironment {
         transaction_generator_node_count,
         libp2p_ipc_encpoint,
         data_pull_interval,
+        debugger_pull_interval,
+        receive_deadline,
         rpc_port,
         cluster_base_url,
         ci_api_url,
@@ -144,5 +194,8 @@ pub fn set_environment() -> AggregatorEnvironment {
         remote_storage_path,
         use_internal_endpoints,
         disable_aggregation,
+        disable_debugger,
+        apply_clock_skew_correction,
+        rpc_api_token,
     }
 }
@@ -1,11 +1,52 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use serde::{Deserialize, Serialize};
 
-use crate::aggregators::{AggregatedBlockTraces, BlockHash, CpnpBlockPublication};
+use crate::{
+    aggregators::{AggregatedBlockTraces, BlockHash, CpnpBlockPublication},
+    storage::percentile,
+};
 
 pub type ComparisonDeltas = BTreeMap<String, f64>;
 
+const MAX_DELTA_DEFAULT: f64 = 1.0;
+const MAX_VIOLATION_RATE_DEFAULT: f64 = 0.05;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ValidationThresholds {
+    /// Maximum absolute difference in seconds between the trace and the debugger receive times of a block
+    pub max_delta: f64,
+    /// Fraction of the checked blocks a node can exceed `max_delta` on and still pass
+    pub max_violation_rate: f64,
+    /// Fail the nodes the debugger or the tracing did not provide data for
+    pub require_all_nodes: bool,
+}
+
+impl Default for ValidationThresholds {
+    fn default() -> Self {
+        Self {
+            max_delta: MAX_DELTA_DEFAULT,
+            max_violation_rate: MAX_VIOLATION_RATE_DEFAULT,
+            require_all_nodes: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct NodeValidationVerdict {
+    pub sample_count: usize,
+    pub avg_delta: f64,
+    /// Percentiles of the absolute deltas
+    pub p50_abs_delta: f64,
+    pub p90_abs_delta: f64,
+    pub p99_abs_delta: f64,
+    pub max_abs_delta: f64,
+    pub violation_count: usize,
+    pub violation_rate: f64,
+    pub missing_count: usize,
+    pub passed: bool,
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct ValidationReport {
     // pub measured_latency_comparison: BTreeMap<String, f64>,
@@ -69,17 +110,22 @@ impl ValidationReport {
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct AggregateValidationReport {
     pub min_recevied_time_comparison: Option<ComparisonDeltas>,
-    // pub avg_recevied_time_comparison: ComparisonDeltas,
+    pub avg_recevied_time_comparison: Option<ComparisonDeltas>,
     pub max_recevied_time_comparison: Option<ComparisonDeltas>,
     pub height_start: Option<usize>,
     pub height_end: Option<usize>,
     pub total_heights_checked: usize,
     pub total_blocks_checked: usize,
     pub all_nodes_present: Option<bool>,
+    pub thresholds: ValidationThresholds,
+    pub node_verdicts: BTreeMap<String, NodeValidationVerdict>,
+    pub failed_nodes: Vec<String>,
+    /// Every node passed, false when there was nothing to check
+    pub passed: bool,
 }
 
 // TODO: unwraps
-pub fn _cross_validate_ipc_with_traces(
+pub fn cross_validate_ipc_with_traces(
     traces: AggregatedBlockTraces,
     ipc_reports: BTreeMap<BlockHash, CpnpBlockPublication>,
     height: usize,
@@ -132,6 +178,7 @@ pub fn _cross_validate_ipc_with_traces(
 
 pub fn aggregate_cross_validations(
     cross_validations: Vec<BTreeMap<String, ValidationReport>>,
+    thresholds: ValidationThresholds,
 ) -> AggregateValidationReport {
     let total_heights_checked = cross_validations.len();
     let height_start = cross_validations
@@ -153,6 +200,22 @@ pub fn aggregate_cross_validations(
         .map(|v| v.all_nodes_present)
         .reduce(|acc, current| acc && current);
 
+    let node_verdicts = node_verdicts(&reports, &thresholds);
+    let failed_nodes: Vec<String> = node_verdicts
+        .iter()
+        .filter(|(_, verdict)| !verdict.passed)
+        .map(|(node, _)| node.clone())
+        .collect();
+    let passed = total_blocks_checked > 0
+        && failed_nodes.is_empty()
+        && (!thresholds.require_all_nodes || all_nodes_present.unwrap_or_default());
+    let avg_agg = (!node_verdicts.is_empty()).then(|| {
+        node_verdicts
+            .iter()
+            .map(|(node, verdict)| (node.clone(), verdict.avg_delta))
+            .collect()
+    });
+
     let min_agg = reports
         .clone()
         .into_iter()
@@ -164,10 +227,141 @@ pub fn aggregate_cross_validations(
     AggregateValidationReport {
         max_recevied_time_comparison: max_agg.map(|r| r.received_time_comparison),
         min_recevied_time_comparison: min_agg.map(|r| r.received_time_comparison),
+        avg_recevied_time_comparison: avg_agg,
         total_heights_checked,
         height_start,
         height_end,
         total_blocks_checked,
         all_nodes_present,
+        thresholds,
+        node_verdicts,
+        failed_nodes,
+        passed,
+    }
+}
+
+fn node_verdicts(
+    reports: &[ValidationReport],
+    thresholds: &ValidationThresholds,
+) -> BTreeMap<String, NodeValidationVerdict> {
+    let mut deltas: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    let mut missing: BTreeMap<String, usize> = BTreeMap::new();
+
+    for report in reports {
+        for (node, delta) in report.received_time_comparison.iter() {
+            deltas.entry(node.clone()).or_default().push(*delta);
+        }
+        for node in report.missing_nodes.iter() {
+            *missing.entry(node.clone()).or_default() += 1;
+        }
+    }
+
+    let nodes: BTreeSet<String> = deltas.keys().chain(missing.keys()).cloned().collect();
+
+    nodes
+        .into_iter()
+        .map(|node| {
+            let node_deltas = deltas.get(&node).cloned().unwrap_or_default();
+            let missing_count = missing.get(&node).copied().unwrap_or_default();
+            let sample_count = node_deltas.len();
+
+            let mut abs_deltas: Vec<f64> = node_deltas.iter().map(|d| d.abs()).collect();
+            abs_deltas.sort_by(|a, b| a.total_cmp(b));
+
+            let violation_count = abs_deltas
+                .iter()
+                .filter(|d| **d > thresholds.max_delta)
+                .count();
+            let violation_rate = if sample_count == 0 {
+                0.0
+            } else {
+                violation_count as f64 / sample_count as f64
+            };
+            let passed = violation_rate <= thresholds.max_violation_rate
+                && (!thresholds.require_all_nodes || missing_count == 0);
+
+            let verdict = NodeValidationVerdict {
+                sample_count,
+                avg_delta: if sample_count == 0 {
+                    0.0
+                } else {
+                    node_deltas.iter().sum::<f64>() / sample_count as f64
+                },
+                p50_abs_delta: percentile(&abs_deltas, 50.0),
+                p90_abs_delta: percentile(&abs_deltas, 90.0),
+                p99_abs_delta: percentile(&abs_deltas, 99.0),
+                max_abs_delta: abs_deltas.last().copied().unwrap_or_default(),
+                violation_count,
+                violation_rate,
+                missing_count,
+                passed,
+            };
+            (node, verdict)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{aggregate_cross_validations, ValidationReport, ValidationThresholds};
+
+    fn report(height: usize, deltas: &[(&str, f64)], missing_nodes: &[&str]) -> ValidationReport {
+        ValidationReport {
+            received_time_comparison: deltas
+                .iter()
+                .map(|(node, delta)| (node.to_string(), *delta))
+                .collect(),
+            all_nodes_present: missing_nodes.is_empty(),
+            missing_nodes: missing_nodes.iter().map(|n| n.to_string()).collect(),
+            height,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cross_validation_verdicts() {
+        let cross_validations: Vec<BTreeMap<String, ValidationReport>> = (1..=10)
+            .map(|height| {
+                // node2 is way off on one height out of ten
+                let node2_delta = if height == 5 { 2.0 } else { 0.1 };
+                let missing: &[&str] = if height == 3 { &["node3"] } else { &[] };
+                BTreeMap::from([(
+                    format!("block{height}"),
+                    report(height, &[("node1", -0.2), ("node2", node2_delta)], missing),
+                )])
+            })
+            .collect();
+
+        let lenient = aggregate_cross_validations(
+            cross_validations.clone(),
+            ValidationThresholds {
+                max_violation_rate: 0.1,
+                ..Default::default()
+            },
+        );
+        assert!(lenient.passed);
+        assert_eq!(10, lenient.total_blocks_checked);
+        let node1 = lenient.node_verdicts.get("node1").unwrap();
+        assert!((node1.avg_delta + 0.2).abs() < 1e-9);
+        assert!((node1.p99_abs_delta - 0.2).abs() < 1e-9);
+        let node2 = lenient.node_verdicts.get("node2").unwrap();
+        assert_eq!(1, node2.violation_count);
+        assert_eq!(2.0, node2.max_abs_delta);
+        assert!((node2.p90_abs_delta - 0.1).abs() < 1e-9);
+        assert_eq!(1, lenient.node_verdicts.get("node3").unwrap().missing_count);
+
+        let strict = aggregate_cross_validations(
+            cross_validations,
+            ValidationThresholds {
+                require_all_nodes: true,
+                ..Default::default()
+            },
+        );
+        assert!(!strict.passed);
+        assert_eq!(vec!["node2", "node3"], strict.failed_nodes);
+
+        assert!(!aggregate_cross_validations(vec![], ValidationThresholds::default()).passed);
     }
 }
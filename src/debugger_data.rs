@@ -18,7 +18,7 @@ impl NodeAddress {
     }
 
     pub fn port(&self) -> String {
-        self.0.split(':').last().unwrap().to_string()
+        self.0.split(':').next_back().unwrap().to_string()
     }
 }
 
@@ -84,7 +84,7 @@ pub struct CpnpCapturedData {
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CpnpEvent {
-    pub r#type: String,
+    pub r#type: CpnpEventType,
     pub peer_id: Option<String>,
     pub peer_address: Option<NodeAddress>,
     pub hash: String,
@@ -93,6 +93,65 @@ pub struct CpnpEvent {
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CpnpMessage {
-    pub r#type: String,
+    pub r#type: CpnpMessageType,
     pub height: usize,
 }
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(from = "String", into = "String")]
+pub enum CpnpEventType {
+    PublishGossip,
+    ReceivedGossip,
+    Unknown(String),
+}
+
+impl From<String> for CpnpEventType {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "publish_gossip" => Self::PublishGossip,
+            "received_gossip" => Self::ReceivedGossip,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<CpnpEventType> for String {
+    fn from(value: CpnpEventType) -> Self {
+        match value {
+            CpnpEventType::PublishGossip => "publish_gossip".to_string(),
+            CpnpEventType::ReceivedGossip => "received_gossip".to_string(),
+            CpnpEventType::Unknown(other) => other,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(from = "String", into = "String")]
+pub enum CpnpMessageType {
+    NewState,
+    SnarkPoolDiff,
+    TransactionPoolDiff,
+    Unknown(String),
+}
+
+impl From<String> for CpnpMessageType {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "new_state" => Self::NewState,
+            "snark_pool_diff" => Self::SnarkPoolDiff,
+            "transaction_pool_diff" => Self::TransactionPoolDiff,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<CpnpMessageType> for String {
+    fn from(value: CpnpMessageType) -> Self {
+        match value {
+            CpnpMessageType::NewState => "new_state".to_string(),
+            CpnpMessageType::SnarkPoolDiff => "snark_pool_diff".to_string(),
+            CpnpMessageType::TransactionPoolDiff => "transaction_pool_diff".to_string(),
+            CpnpMessageType::Unknown(other) => other,
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use futures::{stream, StreamExt};
+use reqwest::StatusCode;
+use tokio::time::sleep;
+use tracing::{error, info, instrument, warn};
+
+use crate::{
+    aggregators::aggregate_first_receive,
+    config::AggregatorEnvironment,
+    cross_validation::cross_validate_ipc_with_traces,
+    debugger_data::{CpnpCapturedData, DebuggerCpnpResponse},
+    error::AggregatorError,
+    executor::state::{AggregatorState, AggregatorStateInner},
+    nodes::{
+        collect_all_urls, collect_all_urls_cluster_ip, get_node_info_from_cluster, ComponentType,
+        Nodes, RequestStats,
+    },
+    storage::AggregatorStorage,
+    AggregatorResult,
+};
+
+/// Queries all the debuggers concurrently, retrying the failed ones.
+/// Returns the collected data, the request stats and the nodes that failed on every retry with the last error
+#[instrument(skip(environment, nodes))]
+async fn pull_debugger_data_cpnp(
+    height: Option<usize>,
+    environment: &AggregatorEnvironment,
+    nodes: Nodes,
+) -> (
+    Vec<DebuggerCpnpResponse>,
+    RequestStats,
+    BTreeMap<String, String>,
+) {
+    let client = reqwest::Client::new();
+
+    const MAX_RETRIES: usize = 3;
+    let mut retries: usize = 0;
+    let nodes_count = nodes.len();
+    let mut nodes_to_query = nodes;
+    let mut final_res: BTreeMap<String, DebuggerCpnpResponse> = BTreeMap::new();
+    let mut failures: BTreeMap<String, String> = BTreeMap::new();
+    let mut total_request_stats = RequestStats::default();
+
+    while retries < MAX_RETRIES {
+        let bodies = stream::iter(nodes_to_query.clone())
+            .map(|(tag, url)| {
+                let client = client.clone();
+                let ipc_endpoint = environment.libp2p_ipc_encpoint.clone();
+                tokio::spawn(async move {
+                    (
+                        tag.clone(),
+                        get_height_data_cpnp(client, height, &url, &ipc_endpoint).await,
+                    )
+                })
+            })
+            .buffer_unordered(150);
+
+        let (collected, failed, timeouts, requests): (
+            BTreeMap<String, DebuggerCpnpResponse>,
+            BTreeMap<String, String>,
+            usize,
+            usize,
+        ) = bodies
+            .fold(
+                (BTreeMap::new(), BTreeMap::new(), 0, 0),
+                |(mut collected, mut failed, mut timeouts, mut requests), b| async move {
+                    match b {
+                        Ok((tag, Ok(res))) => {
+                            let res = res
+                                .into_iter()
+                                .map(|mut e| {
+                                    e.node_tag = tag.to_string();
+                                    e
+                                })
+                                .collect::<Vec<CpnpCapturedData>>();
+                            collected.insert(tag, res);
+                        }
+                        Ok((tag, Err(e))) => {
+                            if let AggregatorError::OutgoingRpcError(reqwest_error) = &e {
+                                if reqwest_error.is_timeout() {
+                                    timeouts += 1;
+                                }
+                            };
+                            failed.insert(tag, e.to_string());
+                        }
+                        Err(e) => error!("Tokio join error: {e}"),
+                    }
+                    requests += 1;
+                    (collected, failed, timeouts, requests)
+                },
+            )
+            .await;
+
+        final_res.extend(collected);
+        failures.extend(failed);
+        failures.retain(|k, _| !final_res.contains_key(k));
+        nodes_to_query.retain(|k, _| !final_res.contains_key(k));
+
+        total_request_stats += RequestStats {
+            request_count: requests,
+            request_timeout_count: timeouts,
+        };
+
+        if nodes_to_query.is_empty() {
+            break;
+        }
+
+        sleep(Duration::from_secs(1)).await;
+        retries += 1;
+    }
+
+    for (tag, reason) in failures.iter() {
+        warn!("Debugger on {tag} failed to provide data, reason: {reason}");
+    }
+
+    info!(
+        "Collected {}/{} debuggers - Timeouts: {}",
+        final_res.len(),
+        nodes_count,
+        total_request_stats.request_timeout_count
+    );
+
+    (
+        final_res.into_values().collect(),
+        total_request_stats,
+        failures,
+    )
+}
+
+async fn get_height_data_cpnp(
+    client: reqwest::Client,
+    height: Option<usize>,
+    base_url: &str,
+    ipc_endpoint: &str,
+) -> AggregatorResult<DebuggerCpnpResponse> {
+    let url = if let Some(height) = height {
+        format!("{}/{}/{}", base_url, ipc_endpoint, height)
+    } else {
+        format!("{}/{}/{}", base_url, ipc_endpoint, "latest")
+    };
+    let res = client
+        .get(url)
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await?;
+
+    let status = res.status();
+
+    if status != StatusCode::OK {
+        return Err(AggregatorError::RpcServerError { status });
+    }
+
+    res.json().await.map_err(|e| e.into())
+}
+
+/// Collects the debugger data for the most recent height with aggregated traces.
+/// Runs separately from the trace aggregation, so slow or failing debuggers do not hold it back
+pub async fn poll_debuggers(
+    state: &AggregatorState,
+    storage: &mut AggregatorStorage,
+    environment: &AggregatorEnvironment,
+) {
+    loop {
+        sleep(environment.debugger_pull_interval).await;
+
+        let current_state = if let Ok(read_state) = state.read() {
+            read_state.clone()
+        } else {
+            info!("No CI build yet!");
+            continue;
+        };
+
+        let AggregatorStateInner {
+            build_number,
+            build_nodes,
+            enable_aggregation,
+            ..
+        } = current_state;
+
+        if !enable_aggregation {
+            continue;
+        }
+
+        let build_storage = if let Ok(Some(build_storage)) = storage.get(build_number) {
+            build_storage
+        } else {
+            continue;
+        };
+
+        // the traces tell us which blocks were produced on the height, so we follow them
+        let (height, block_traces) =
+            if let Some((height, block_traces)) = build_storage.trace_storage.last_key_value() {
+                (*height, block_traces.clone())
+            } else {
+                info!("No traces yet, skipping debugger polling");
+                continue;
+            };
+
+        let (debugger_urls, node_infos) = if environment.use_internal_endpoints {
+            (
+                collect_all_urls_cluster_ip(&build_nodes, ComponentType::Debugger),
+                build_nodes,
+            )
+        } else {
+            let (node_infos, _) =
+                get_node_info_from_cluster(collect_all_urls(environment, ComponentType::Graphql))
+                    .await;
+            (
+                collect_all_urls(environment, ComponentType::Debugger),
+                node_infos,
+            )
+        };
+
+        // build a map that maps peer_id to tag
+        let peer_id_to_tag_map: BTreeMap<String, String> = node_infos
+            .iter()
+            .map(|(k, v)| {
+                (
+                    v.daemon_status.addrs_and_ports.peer.peer_id.clone(),
+                    k.to_string(),
+                )
+            })
+            .collect();
+
+        let tag_to_block_hash_map: BTreeMap<String, String> = block_traces
+            .inner()
+            .values()
+            .flatten()
+            .filter(|trace| trace.is_producer)
+            .map(|trace| (trace.node.clone(), trace.block_hash.clone()))
+            .collect();
+
+        // the nodes with a debugger that answered, a failed debugger says nothing about its node
+        let mut cluster_nodes: Vec<String> = debugger_urls.keys().cloned().collect();
+
+        info!("Polling debuggers for height {height}");
+        let (ipc_data, request_stats, failures) =
+            pull_debugger_data_cpnp(Some(height), environment, debugger_urls).await;
+        cluster_nodes.retain(|node| !failures.contains_key(node));
+
+        let res = storage.update(build_number, |build_storage| {
+            build_storage.update_debugger_stats(request_stats, &failures)
+        });
+        if let Err(e) = res {
+            warn!("Failed to store debugger request stats: {e}");
+        }
+
+        let aggregated_ipc_data = match aggregate_first_receive(
+            ipc_data,
+            &peer_id_to_tag_map,
+            &tag_to_block_hash_map,
+            &cluster_nodes,
+            environment.receive_deadline.as_micros() as u64,
+        ) {
+            Ok((_, aggregate_data)) => aggregate_data,
+            Err(e) => {
+                warn!("{}", e);
+                continue;
+            }
+        };
+
+        let cross_validation_report =
+            cross_validate_ipc_with_traces(block_traces, aggregated_ipc_data.clone(), height);
+
+        let res = storage.update(build_number, |build_storage| {
+            build_storage.store_debugger_data(height, aggregated_ipc_data, cross_validation_report)
+        });
+        if let Err(e) = res {
+            warn!("Failed to store debugger data: {e}");
+        }
+        info!("Debugger data for height {height} stored");
+    }
+}
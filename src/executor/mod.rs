@@ -1,84 +1,24 @@
-use std::{collections::BTreeMap, time::Duration};
-
 use tokio::time::sleep;
-use tracing::{info, instrument, warn};
+use tracing::{info, warn};
 
 use crate::{
-    aggregators::aggregate_block_traces,
+    aggregators::{aggregate_best_chains, aggregate_block_traces},
     config::AggregatorEnvironment,
-    debugger_data::{CpnpCapturedData, DebuggerCpnpResponse},
     executor::state::AggregatorStateInner,
     nodes::{
         collect_all_urls, collect_all_urls_cluster_ip, collect_producer_urls,
-        collect_producer_urls_cluster_ip, get_best_chain, get_block_trace_from_cluster,
-        get_most_recent_produced_blocks, get_node_info_from_cluster, get_seed_url,
-        get_seed_url_cluster_ip, ComponentType, Nodes, RequestStats,
+        collect_producer_urls_cluster_ip, filter_best_chain_nodes, get_best_chains_from_cluster,
+        get_block_trace_from_cluster, get_most_recent_produced_blocks, get_node_info_from_cluster,
+        ComponentType, RequestStats,
     },
     storage::AggregatorStorage,
-    AggregatorResult,
 };
 
 use self::state::AggregatorState;
 
+pub mod debugger;
 pub mod state;
 
-#[instrument(skip(environment, nodes))]
-async fn pull_debugger_data_cpnp(
-    height: Option<usize>,
-    environment: &AggregatorEnvironment,
-    nodes: Nodes,
-) -> Vec<DebuggerCpnpResponse> {
-    let mut collected: Vec<DebuggerCpnpResponse> = vec![];
-
-    for (tag, url) in nodes.iter() {
-        // info!("Pulling {}", url);
-        match _get_height_data_cpnp(height, url, environment).await {
-            Ok(data) => {
-                let modified_data = data
-                    .into_iter()
-                    .map(|mut e| {
-                        // e.node_address = NodeAddress(node_infos.get(tag).unwrap().daemon_status.addrs_and_ports.external_ip.clone());
-                        e.node_tag = tag.to_string();
-                        e
-                    })
-                    .collect::<Vec<CpnpCapturedData>>();
-                collected.push(modified_data);
-            }
-            Err(e) => warn!("{} failed to provide data, reson: {}", url, e),
-        }
-    }
-
-    collected
-}
-
-async fn _get_height_data_cpnp(
-    height: Option<usize>,
-    base_url: &str,
-    environment: &AggregatorEnvironment,
-) -> AggregatorResult<DebuggerCpnpResponse> {
-    let url = if let Some(height) = height {
-        format!(
-            "{}/{}/{}",
-            base_url, environment.libp2p_ipc_encpoint, height
-        )
-    } else {
-        format!(
-            "{}/{}/{}",
-            base_url, environment.libp2p_ipc_encpoint, "latest"
-        )
-    };
-    // reqwest::get(url).await?.json().await.map_err(|e| e.into())
-    let client = reqwest::Client::new();
-    client
-        .get(url)
-        .timeout(Duration::from_secs(5))
-        .send()
-        .await?
-        .json()
-        .await
-        .map_err(|e| e.into())
-}
-
 pub async fn poll_node_traces(
     state: &AggregatorState,
     storage: &mut AggregatorStorage,
@@ -117,24 +57,21 @@ pub async fn poll_node_traces(
         let mut total_request_stats = RequestStats::default();
 
         // Collect urls based on wether we want to access the nodes directly (only when aggregator is running inside the cluster) or trough the proxy
-        let (graphql_urls, tracing_urls, _debugger_urls, producer_tracing_urls, seed_url) =
+        let (graphql_urls, tracing_urls, producer_tracing_urls) =
             if environment.use_internal_endpoints {
                 (
                     collect_all_urls_cluster_ip(&build_nodes, ComponentType::Graphql),
                     collect_all_urls_cluster_ip(&build_nodes, ComponentType::InternalTracing),
-                    collect_all_urls_cluster_ip(&build_nodes, ComponentType::Debugger),
                     collect_producer_urls_cluster_ip(&build_nodes, ComponentType::InternalTracing),
-                    get_seed_url_cluster_ip(&build_nodes, ComponentType::Graphql),
                 )
             } else {
                 (
                     collect_all_urls(environment, ComponentType::Graphql),
                     collect_all_urls(environment, ComponentType::InternalTracing),
-                    collect_all_urls(environment, ComponentType::Debugger),
                     collect_producer_urls(environment, ComponentType::InternalTracing),
-                    get_seed_url(environment, ComponentType::Graphql),
                 )
             };
+        let best_chain_urls = filter_best_chain_nodes(&graphql_urls);
 
         info!("Collecting produced blocks...");
         let (mut blocks_on_most_recent_height, producer_trace_timeouts) =
@@ -173,30 +110,8 @@ pub async fn poll_node_traces(
         // println!("INF: {:#?}", node_infos);
         info!("Information collected");
         total_request_stats += node_info_timeouts;
-
-        // TODO: REENABLE DEBUGGER DATA
-        // build a map that maps peer_id to tag
-        // let peer_id_to_tag_map: BTreeMap<String, String> = node_infos
-        //     .iter()
-        //     .map(|(k, v)| {
-        //         (
-        //             v.daemon_status.addrs_and_ports.peer.peer_id.clone(),
-        //             k.to_string(),
-        //         )
-        //     })
-        //     .collect();
-
-        // let tag_to_block_hash_map: BTreeMap<String, String> = blocks_on_most_recent_height
-        //     .iter()
-        //     .map(|(tag, produced_block)| {
-        //         // let peer_id = tag_to_peer_id_map.get(tag).unwrap();
-        //         (tag.to_string(), produced_block.state_hash.clone())
-        //     })
-        //     .collect();
-
-        // Optimization, only grab traces once for the same block hash
-        // blocks_on_most_recent_height.sort_unstable();
-        // blocks_on_most_recent_height.dedup();
+        build_storage.record_node_statuses(height, &node_infos);
+        build_storage.record_snark_pool(height, &node_infos);
 
         for (_, produced_block) in blocks_on_most_recent_height.clone() {
             info!(
@@ -209,7 +124,16 @@ pub async fn poll_node_traces(
             info!("Traces collected");
             info!("Aggregating trace data");
             // println!("TRACES KEYS: {:#?}", trace.keys());
-            match aggregate_block_traces(height, &produced_block.state_hash, &node_infos, trace) {
+            let clock_skews = environment
+                .apply_clock_skew_correction
+                .then_some(&build_storage.clock_skews);
+            match aggregate_block_traces(
+                height,
+                &produced_block.state_hash,
+                &node_infos,
+                trace,
+                clock_skews,
+            ) {
                 Ok(data) => {
                     block_traces.insert(produced_block.state_hash.clone(), data);
                 }
@@ -219,57 +143,41 @@ pub async fn poll_node_traces(
             info!("Trace aggregation finished");
         }
 
-        // TODO: REENABLE DEBUGGER DATA
-        // TODO: move this to a separate thread?
-        // info!("Polling debuggers for height {height}");
-
-        // let ipc_data = pull_debugger_data_cpnp(Some(height), environment, debugger_urls).await;
-
-        // let (_, aggregated_ipc_data) =
-        //     match aggregate_first_receive(ipc_data, &peer_id_to_tag_map, &tag_to_block_hash_map) {
-        //         Ok((height, aggregate_data)) => (height, aggregate_data),
-        //         Err(e) => {
-        //             warn!("{}", e);
-        //             (height, Default::default())
-        //         }
-        //     };
-
-        // // also do the cross_validation
-        // let cross_validation_report = cross_validate_ipc_with_traces(
-        //     block_traces.clone(),
-        //     aggregated_ipc_data.clone(),
-        //     height,
-        // );
-
         info!("Updating best chain");
-        match get_best_chain(&seed_url).await {
-            Ok(best_chain) => {
-                best_chain.into_iter().for_each(|best_chain_block| {
-                    let height = best_chain_block
-                        .protocol_state
-                        .consensus_state
-                        .block_height
-                        .parse()
-                        .unwrap_or_default();
-                    build_storage
-                        .best_chain
-                        .insert(height, best_chain_block.state_hash);
-                });
+        let (best_chains, best_chain_timeouts) =
+            get_best_chains_from_cluster(best_chain_urls).await;
+        total_request_stats += best_chain_timeouts;
+        if best_chains.is_empty() {
+            warn!("No node provided its best chain");
+        } else {
+            let consensus = aggregate_best_chains(best_chains);
+            for (tag, tip) in consensus.divergent_nodes.iter() {
+                warn!(
+                    "{tag} diverged from the majority at height {}: {} != {}",
+                    tip.height, tip.state_hash, tip.majority_hash
+                );
             }
-            Err(e) => {
-                warn!("{e}")
+            if let Some(reorg) = build_storage.update_best_chain_consensus(consensus) {
+                warn!(
+                    "Reorg detected at height {} with depth {}: {} -> {}",
+                    reorg.height, reorg.depth, reorg.old_hash, reorg.new_hash
+                );
             }
         }
 
         build_storage.update_summary(height, &block_traces, total_request_stats);
 
         // store aggregated data
-        build_storage.store_data(
-            height,
-            block_traces,
-            BTreeMap::new(), // TODO: REENABLE DEBUGGER DATA
-            BTreeMap::new(), // TODO: REENABLE DEBUGGER DATA
-        );
-        let _ = storage.insert(build_number, build_storage);
+        build_storage.store_data(height, block_traces);
+        let res = storage.update(build_number, |stored| {
+            // the debugger worker writes into the same build concurrently, keep its data
+            build_storage.take_debugger_data(stored);
+            // the baseline can be pinned through the RPC in the meantime
+            build_storage.baseline_names = std::mem::take(&mut stored.baseline_names);
+            *stored = build_storage;
+        });
+        if let Err(e) = res {
+            warn!("Failed to store trace data: {e}");
+        }
     }
 }
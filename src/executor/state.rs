@@ -60,11 +60,9 @@ pub async fn poll_drone(
                     }
                 }
 
-                if let Ok(Some(mut build_storage)) = storage.get(build.number) {
+                let _ = storage.update(build.number, |build_storage| {
                     build_storage.build_info = build.clone();
-
-                    let _ = storage.insert(build.number, build_storage);
-                }
+                });
 
                 // TODO: optimize this part
                 // TODO: REENABLE THIS!!
@@ -99,7 +97,7 @@ pub async fn query_latest_build(
 
     let res: Vec<BuildInfo> = client.get(url).send().await?.json().await?;
 
-    Ok(res.get(0).cloned().unwrap_or_default())
+    Ok(res.first().cloned().unwrap_or_default())
 }
 
 #[instrument(skip(environment))]
@@ -4,6 +4,7 @@ use tracing::info;
 
 use crate::{
     executor::{
+        debugger::poll_debuggers,
         poll_node_traces,
         state::{poll_drone, poll_info_from_cluster},
     },
@@ -38,6 +39,19 @@ async fn main() {
 
     let (state, aggregator_storage) = remote_storage.load_storage();
 
+    let debugger_handle = if !environment.disable_aggregation && !environment.disable_debugger {
+        let mut t_aggregator_storage = aggregator_storage.clone();
+        let t_state = state.clone();
+        let t_environment = environment.clone();
+        info!("Creating debugger polling thread");
+        let handle = tokio::spawn(async move {
+            poll_debuggers(&t_state, &mut t_aggregator_storage, &t_environment).await
+        });
+        Some(handle)
+    } else {
+        None
+    };
+
     let (node_info_handle, drone_handle, aggregator_handle) = if !environment.disable_aggregation {
         let node_info_handle = if environment.use_internal_endpoints {
             info!("Creating ip retrieval thread");
@@ -86,7 +100,11 @@ async fn main() {
 
     info!("Creating rpc server");
     let t_aggregator_storage = aggregator_storage.clone();
-    let rpc_server_handle = rpc::spawn_rpc_server(environment.rpc_port, t_aggregator_storage);
+    let rpc_server_handle = rpc::spawn_rpc_server(
+        environment.rpc_port,
+        environment.rpc_api_token.clone(),
+        t_aggregator_storage,
+    );
 
     let mut signal_stream =
         tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
@@ -110,6 +128,7 @@ async fn main() {
     drop(node_info_handle);
     drop(drone_handle);
     drop(aggregator_handle);
+    drop(debugger_handle);
     drop(rpc_server_handle);
 
     info!("Shutdown successfull!");
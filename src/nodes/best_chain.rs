@@ -1,12 +1,18 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use futures::{stream, StreamExt};
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
 
 use crate::{aggregators::BlockHash, error::AggregatorError, AggregatorResult};
 
-use super::{query_node, GraphqlResponse};
+use super::{
+    query_node, GraphqlResponse, Nodes, RequestStats, PRODUCER_NODE_COMPONENT, SEED_NODE_COMPONENT,
+};
 
-const BEST_CHAIN_PAYLOAD: &str =
-    r#"{"query": "{ bestChain { stateHash protocolState { consensusState { blockHeight } } } }" }"#;
+const BEST_CHAIN_PAYLOAD: &str = r#"{"query": "{ bestChain { stateHash protocolState { previousStateHash consensusState { blockHeight } } } }" }"#;
 
 pub type BestChain = Vec<BestChainBlock>;
 
@@ -26,6 +32,8 @@ pub struct BestChainBlock {
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ProtocolState {
+    #[serde(default)]
+    pub previous_state_hash: BlockHash,
     pub consensus_state: ConsensusState,
 }
 
@@ -35,11 +43,88 @@ pub struct ConsensusState {
     pub block_height: String,
 }
 
-pub async fn get_best_chain(url: &str) -> AggregatorResult<Vec<BestChainBlock>> {
+/// Nodes used to establish the best chain consensus: the seeds and the producers
+pub fn filter_best_chain_nodes(nodes: &Nodes) -> Nodes {
+    nodes
+        .iter()
+        .filter(|(tag, _)| {
+            tag.starts_with(SEED_NODE_COMPONENT) || tag.starts_with(PRODUCER_NODE_COMPONENT)
+        })
+        .map(|(tag, url)| (tag.clone(), url.clone()))
+        .collect()
+}
+
+pub async fn get_best_chains_from_cluster(
+    nodes: Nodes,
+) -> (BTreeMap<String, BestChain>, RequestStats) {
     let client = reqwest::Client::new();
 
-    // let url = "http://1.k8.openmina.com:31355/seed1/graphql";
+    const MAX_RETRIES: usize = 3;
+    let mut retries: usize = 0;
+    let nodes_count = nodes.len();
+    let mut nodes_to_query = nodes;
+    let mut final_res: BTreeMap<String, BestChain> = BTreeMap::new();
+    let mut total_request_stats = RequestStats::default();
+
+    while retries < MAX_RETRIES {
+        let bodies = stream::iter(nodes_to_query.clone())
+            .map(|(tag, url)| {
+                let client = client.clone();
+                tokio::spawn(async move { (tag.clone(), get_best_chain(client, &url).await) })
+            })
+            .buffer_unordered(150);
+
+        let (collected, timeouts, requests): (BTreeMap<String, BestChain>, usize, usize) = bodies
+            .fold(
+                (BTreeMap::<String, BestChain>::new(), 0, 0),
+                |(mut collected, mut timeouts, mut requests), b| async move {
+                    match b {
+                        Ok((tag, Ok(res))) => {
+                            collected.insert(tag, res);
+                        }
+                        Ok((tag, Err(e))) => {
+                            warn!("Error requestig best chain from {tag}, reason: {}", e);
+                            if let AggregatorError::OutgoingRpcError(reqwest_error) = e {
+                                if reqwest_error.is_timeout() {
+                                    timeouts += 1;
+                                }
+                            };
+                        }
+                        Err(e) => error!("Tokio join error: {e}"),
+                    }
+                    requests += 1;
+                    (collected, timeouts, requests)
+                },
+            )
+            .await;
+
+        final_res.extend(collected);
+        nodes_to_query.retain(|k, _| !final_res.contains_key(k));
+
+        total_request_stats += RequestStats {
+            request_count: requests,
+            request_timeout_count: timeouts,
+        };
+
+        if nodes_to_query.is_empty() {
+            break;
+        }
+
+        sleep(Duration::from_secs(1)).await;
+        retries += 1;
+    }
+
+    info!(
+        "Collected {}/{} best chains - Timeouts: {}",
+        final_res.len(),
+        nodes_count,
+        total_request_stats.request_timeout_count
+    );
+
+    (final_res, total_request_stats)
+}
 
+pub async fn get_best_chain(client: reqwest::Client, url: &str) -> AggregatorResult<BestChain> {
     let res = query_node(client, url, BEST_CHAIN_PAYLOAD.to_string()).await?;
 
     let status = res.status();
@@ -143,18 +143,6 @@ pub fn collect_all_urls(
     res
 }
 
-pub fn get_seed_url(environment: &AggregatorEnvironment, component_type: ComponentType) -> String {
-    let component = match component_type {
-        ComponentType::Graphql => GRAPHQL_COMPONENT,
-        ComponentType::Debugger => DEBUGGER_COMPONENT,
-        ComponentType::InternalTracing => INTERNAL_TRACING_COMPONENT,
-    };
-
-    let cluster_base_url = environment.cluster_base_url.to_string();
-    let seed_label = format!("{}{}", SEED_NODE_COMPONENT, 1);
-    format!("{}/{}/{}", cluster_base_url, seed_label, component)
-}
-
 pub fn collect_producer_urls(
     environment: &AggregatorEnvironment,
     component: ComponentType,
@@ -203,26 +191,6 @@ pub fn collect_producer_urls_cluster_ip(
         .collect()
 }
 
-pub fn get_seed_url_cluster_ip(build_nodes: &BuildNodes, component_type: ComponentType) -> String {
-    let (component, port) = match component_type {
-        // TODO: rework
-        ComponentType::Graphql => ("/graphql", "3085"),
-        ComponentType::Debugger => ("", "80"),
-        ComponentType::InternalTracing => ("/graphql", "8000"),
-    };
-
-    build_nodes
-        .iter()
-        .find(|(tag, _)| tag.contains("seed1"))
-        .map(|(_, data)| {
-            format!(
-                "http://{}:{port}{component}",
-                data.daemon_status.addrs_and_ports.external_ip
-            )
-        })
-        .unwrap_or_default()
-}
-
 pub fn collect_all_urls_cluster_ip(
     build_nodes: &BuildNodes,
     component_type: ComponentType,
@@ -1,4 +1,4 @@
-use std::{collections::BTreeMap, time::Duration};
+use std::{collections::BTreeMap, ops::AddAssign, time::Duration};
 
 use futures::{stream, StreamExt};
 use reqwest::StatusCode;
@@ -61,7 +61,7 @@ pub struct DiffLogPart {
     pub discarded_commands: DiscardedCommands,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct DiscardedCommands {
     pub insufficient_work: usize,
     pub insufficient_space: usize,
@@ -73,6 +73,13 @@ impl DiscardedCommands {
     }
 }
 
+impl AddAssign<&DiscardedCommands> for DiscardedCommands {
+    fn add_assign(&mut self, rhs: &DiscardedCommands) {
+        self.insufficient_work += rhs.insufficient_work;
+        self.insufficient_space += rhs.insufficient_space;
+    }
+}
+
 // pub type GlobalSlot = Vec<String>;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
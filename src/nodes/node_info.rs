@@ -10,7 +10,7 @@ use crate::{error::AggregatorError, nodes::RequestStats, AggregatorResult};
 
 use super::{query_node, GraphqlResponse, Nodes};
 
-const NODE_INFO_PAYLOAD: &str = r#"{"query": "{ daemonStatus { addrsAndPorts { externalIp, peer { peerId } } syncStatus metrics { transactionPoolSize transactionsAddedToPool transactionPoolDiffReceived transactionPoolDiffBroadcasted } } snarkPool { prover } }" }"#;
+const NODE_INFO_PAYLOAD: &str = r#"{"query": "{ daemonStatus { addrsAndPorts { externalIp, peer { peerId } } syncStatus metrics { transactionPoolSize transactionsAddedToPool transactionPoolDiffReceived transactionPoolDiffBroadcasted } uptimeSecs blockProductionKeys peers { peerId } highestBlockLengthReceived catchupStatus consensusTimeNow { epoch slot globalSlot } } snarkPool { prover } }" }"#;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -19,14 +19,14 @@ pub struct DaemonStatusData {
     pub snark_pool: Vec<SnarkPoolElement>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct AddrsAndPorts {
     pub external_ip: String,
     pub peer: Peer,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Peer {
     pub peer_id: String,
@@ -47,26 +47,60 @@ pub struct DaemonMetrics {
     pub transaction_pool_diff_received: usize,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsensusTime {
+    pub epoch: String,
+    pub slot: String,
+    pub global_slot: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct DaemonStatus {
     pub addrs_and_ports: AddrsAndPorts,
     pub sync_status: String,
     pub metrics: DaemonMetrics,
+    #[serde(default)]
+    pub uptime_secs: Option<u64>,
+    #[serde(default)]
+    pub block_production_keys: Vec<String>,
+    /// Only counted in the slim version, see `DaemonStatusDataSlim::peer_count`
+    #[serde(default)]
+    pub peers: Vec<Peer>,
+    #[serde(default)]
+    pub highest_block_length_received: Option<usize>,
+    /// The states of the catchup jobs, null when the node is not catching up
+    #[serde(default)]
+    pub catchup_status: Option<Vec<String>>,
+    #[serde(default)]
+    pub consensus_time_now: Option<ConsensusTime>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct DaemonStatusDataSlim {
     pub daemon_status: DaemonStatus,
     pub snark_pool: usize,
+    #[serde(default)]
+    pub peer_count: usize,
+    /// Snark pool work count per prover
+    #[serde(default)]
+    pub snark_pool_provers: BTreeMap<String, usize>,
 }
 
 impl From<DaemonStatusData> for DaemonStatusDataSlim {
-    fn from(value: DaemonStatusData) -> Self {
+    fn from(mut value: DaemonStatusData) -> Self {
+        let peer_count = std::mem::take(&mut value.daemon_status.peers).len();
+        let mut snark_pool_provers = BTreeMap::new();
+        for work in value.snark_pool.iter() {
+            *snark_pool_provers.entry(work.prover.clone()).or_default() += 1;
+        }
         Self {
             daemon_status: value.daemon_status,
             snark_pool: value.snark_pool.len(),
+            peer_count,
+            snark_pool_provers,
         }
     }
 }
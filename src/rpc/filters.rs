@@ -6,21 +6,29 @@ use super::handlers::{
     aggregate_cross_validations_handler, cross_validate_ipc_with_traces_handler,
     get_aggregated_block_receive_data, get_aggregated_block_receive_data_latest,
     get_aggregated_block_trace_data, get_aggregated_block_trace_data_latest,
-    get_aggregated_block_trace_data_latest_height, get_block_summaries, get_build_summaries,
-    get_build_summary, get_cross_validations_count_handler, BuildsQueryOptions, QueryOptions,
+    get_aggregated_block_trace_data_latest_height, get_baselines, get_block_summaries,
+    get_build_summaries, get_build_summary, get_build_trend, get_clock_skews,
+    get_cross_validations_count_handler, get_fork_report, get_height_series, get_node_health,
+    get_node_health_events, get_node_profile, get_node_ranking, get_propagation_graph_dot,
+    get_propagation_graphs, get_snark_pool_series, get_sync_status_transitions, get_trends,
+    pin_baseline, unpin_baseline, BaselineQueryOptions, BuildsQueryOptions,
+    HeightRangeQueryOptions, NodeRankingQueryOptions, QueryOptions,
 };
 
 pub fn filters(
     storage: AggregatorStorage,
+    api_token: Option<String>,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     // Allow cors from any origin
     let cors = warp::cors()
         .allow_any_origin()
-        .allow_headers(vec!["content-type"])
-        .allow_methods(vec!["GET"]);
+        .allow_headers(vec!["content-type", "authorization"])
+        .allow_methods(vec!["GET", "PUT", "DELETE"]);
 
     block_receive_aggregation(storage.clone())
         .or(block_receive_aggregation_latest(storage.clone()))
+        .or(propagation_graphs(storage.clone()))
+        .or(propagation_graph_dot(storage.clone()))
         .or(block_traces_aggregation(storage.clone()))
         .or(block_traces_aggregation_latest(storage.clone()))
         .or(block_traces_aggregation_latest_height(storage.clone()))
@@ -28,8 +36,22 @@ pub fn filters(
         .or(cross_validation_counts(storage.clone()))
         .or(aggregate_cross_validations_filter(storage.clone()))
         .or(build_summaries(storage.clone()))
+        .or(build_trend(storage.clone()))
+        .or(trends(storage.clone()))
         .or(build_summary(storage.clone()))
-        .or(block_summaries(storage))
+        .or(block_summaries(storage.clone()))
+        .or(fork_report(storage.clone()))
+        .or(height_series(storage.clone()))
+        .or(snark_pool_series(storage.clone()))
+        .or(node_ranking(storage.clone()))
+        .or(node_profile(storage.clone()))
+        .or(node_health(storage.clone()))
+        .or(sync_status_transitions(storage.clone()))
+        .or(node_health_events(storage.clone()))
+        .or(clock_skews(storage.clone()))
+        .or(baselines(storage.clone()))
+        .or(pin_baseline_filter(storage.clone(), api_token.clone()))
+        .or(unpin_baseline_filter(storage, api_token))
         .with(cors)
 }
 
@@ -43,6 +65,26 @@ fn build_summaries(
         .and_then(get_build_summaries)
 }
 
+fn build_trend(
+    storage: AggregatorStorage,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("builds" / "trend")
+        .and(warp::get())
+        .and(warp::query::<BuildsQueryOptions>())
+        .and(with_storage(storage))
+        .and_then(get_build_trend)
+}
+
+fn trends(
+    storage: AggregatorStorage,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("trends")
+        .and(warp::get())
+        .and(warp::query::<BuildsQueryOptions>())
+        .and(with_storage(storage))
+        .and_then(get_trends)
+}
+
 fn build_summary(
     storage: AggregatorStorage,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
@@ -53,6 +95,39 @@ fn build_summary(
         .and_then(get_build_summary)
 }
 
+fn baselines(
+    storage: AggregatorStorage,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("baselines")
+        .and(warp::get())
+        .and(with_storage(storage))
+        .and_then(get_baselines)
+}
+
+fn pin_baseline_filter(
+    storage: AggregatorStorage,
+    api_token: Option<String>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("builds" / usize / "baseline")
+        .and(warp::put())
+        .and(warp::query::<BaselineQueryOptions>())
+        .and(with_authorization(api_token))
+        .and(with_storage(storage))
+        .and_then(pin_baseline)
+}
+
+fn unpin_baseline_filter(
+    storage: AggregatorStorage,
+    api_token: Option<String>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("builds" / usize / "baseline")
+        .and(warp::delete())
+        .and(warp::query::<BaselineQueryOptions>())
+        .and(with_authorization(api_token))
+        .and(with_storage(storage))
+        .and_then(unpin_baseline)
+}
+
 fn block_summaries(
     storage: AggregatorStorage,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
@@ -62,6 +137,90 @@ fn block_summaries(
         .and_then(get_block_summaries)
 }
 
+fn height_series(
+    storage: AggregatorStorage,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("builds" / usize / "heights")
+        .and(warp::get())
+        .and(warp::query::<HeightRangeQueryOptions>())
+        .and(with_storage(storage))
+        .and_then(get_height_series)
+}
+
+fn node_ranking(
+    storage: AggregatorStorage,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("builds" / usize / "nodes")
+        .and(warp::get())
+        .and(warp::query::<NodeRankingQueryOptions>())
+        .and(with_storage(storage))
+        .and_then(get_node_ranking)
+}
+
+fn node_profile(
+    storage: AggregatorStorage,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("builds" / usize / "nodes" / String)
+        .and(warp::get())
+        .and(with_storage(storage))
+        .and_then(get_node_profile)
+}
+
+fn node_health(
+    storage: AggregatorStorage,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("builds" / usize / "nodes" / String / "health")
+        .and(warp::get())
+        .and(with_storage(storage))
+        .and_then(get_node_health)
+}
+
+fn sync_status_transitions(
+    storage: AggregatorStorage,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("builds" / usize / "sync_status_transitions")
+        .and(warp::get())
+        .and(with_storage(storage))
+        .and_then(get_sync_status_transitions)
+}
+
+fn node_health_events(
+    storage: AggregatorStorage,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("builds" / usize / "health_events")
+        .and(warp::get())
+        .and(with_storage(storage))
+        .and_then(get_node_health_events)
+}
+
+fn snark_pool_series(
+    storage: AggregatorStorage,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("builds" / usize / "snark_pool")
+        .and(warp::get())
+        .and(warp::query::<HeightRangeQueryOptions>())
+        .and(with_storage(storage))
+        .and_then(get_snark_pool_series)
+}
+
+fn fork_report(
+    storage: AggregatorStorage,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("builds" / usize / "forks")
+        .and(warp::get())
+        .and(with_storage(storage))
+        .and_then(get_fork_report)
+}
+
+fn clock_skews(
+    storage: AggregatorStorage,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("builds" / usize / "clock_skews")
+        .and(warp::get())
+        .and(with_storage(storage))
+        .and_then(get_clock_skews)
+}
+
 fn block_receive_aggregation(
     storage: AggregatorStorage,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
@@ -71,6 +230,24 @@ fn block_receive_aggregation(
         .and_then(get_aggregated_block_receive_data)
 }
 
+fn propagation_graphs(
+    storage: AggregatorStorage,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("builds" / usize / "ipc_blocks" / usize / "graph")
+        .and(warp::get())
+        .and(with_storage(storage))
+        .and_then(get_propagation_graphs)
+}
+
+fn propagation_graph_dot(
+    storage: AggregatorStorage,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("builds" / usize / "ipc_blocks" / usize / "graph" / String / "dot")
+        .and(warp::get())
+        .and(with_storage(storage))
+        .and_then(get_propagation_graph_dot)
+}
+
 fn block_receive_aggregation_latest(
     storage: AggregatorStorage,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
@@ -135,6 +312,26 @@ fn cross_validation_counts(
         .and_then(get_cross_validations_count_handler)
 }
 
+/// Whether the request carries the configured API token as a bearer token, never without a configured token
+fn with_authorization(
+    api_token: Option<String>,
+) -> impl Filter<Extract = (bool,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization").map(move |header: Option<String>| {
+        match (&api_token, header) {
+            (Some(api_token), Some(header)) if !api_token.is_empty() => header
+                .strip_prefix("Bearer ")
+                .map(|token| constant_time_eq(token.as_bytes(), api_token.as_bytes()))
+                .unwrap_or(false),
+            _ => false,
+        }
+    })
+}
+
+/// Compares every byte regardless of where the first mismatch is, so the time taken doesn't reveal the token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
 fn with_storage(
     storage: AggregatorStorage,
 ) -> impl Filter<Extract = (AggregatorStorage,), Error = std::convert::Infallible> + Clone {
@@ -161,3 +358,28 @@ fn with_storage(
 // }
 
 // cross_validation_storage
+
+#[cfg(test)]
+mod tests {
+    use super::with_authorization;
+
+    #[tokio::test]
+    async fn test_authorization() {
+        let authorized = |api_token: Option<&str>, header: Option<&str>| {
+            let filter = with_authorization(api_token.map(|token| token.to_string()));
+            let request = header
+                .into_iter()
+                .fold(warp::test::request(), |request, header| {
+                    request.header("authorization", header)
+                });
+            async move { request.filter(&filter).await.unwrap() }
+        };
+
+        assert!(authorized(Some("secret"), Some("Bearer secret")).await);
+        assert!(!authorized(Some("secret"), Some("Bearer secre")).await);
+        assert!(!authorized(Some("secret"), Some("secret")).await);
+        assert!(!authorized(Some("secret"), None).await);
+        assert!(!authorized(Some(""), Some("Bearer ")).await);
+        assert!(!authorized(None, Some("Bearer ")).await);
+    }
+}
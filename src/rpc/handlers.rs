@@ -2,10 +2,15 @@ use std::collections::BTreeMap;
 
 use crate::{
     aggregators::{
-        BlockTraceAggregatorReport, CpnpBlockPublication, CpnpBlockPublicationFlattened,
+        to_dot, BlockHash, BlockTraceAggregatorReport, CpnpBlockPublication,
+        CpnpBlockPublicationFlattened, PropagationGraph,
+    },
+    cross_validation::{aggregate_cross_validations, ValidationReport, ValidationThresholds},
+    storage::{
+        find_named_baseline, rank_nodes, rolling_baseline_builds, trend_series, AggregatorStorage,
+        BlockSummary, BuildInfo, BuildStorage, BuildTrendPoint, ComparisonBaseline, ForkReport,
+        RegressionThresholds, SnarkPoolSnapshot,
     },
-    cross_validation::{aggregate_cross_validations, ValidationReport},
-    storage::{AggregatorStorage, BlockSummary, BuildStorage},
 };
 use itertools::Itertools;
 use reqwest::StatusCode;
@@ -15,6 +20,22 @@ use serde::Deserialize;
 #[allow(dead_code)]
 pub struct QueryOptions {
     count: Option<usize>,
+    tolerance: Option<f64>,
+    max_violation_rate: Option<f64>,
+    require_all_nodes: Option<bool>,
+}
+
+impl QueryOptions {
+    fn validation_thresholds(&self) -> ValidationThresholds {
+        let default = ValidationThresholds::default();
+        ValidationThresholds {
+            max_delta: self.tolerance.unwrap_or(default.max_delta),
+            max_violation_rate: self
+                .max_violation_rate
+                .unwrap_or(default.max_violation_rate),
+            require_all_nodes: self.require_all_nodes.unwrap_or(default.require_all_nodes),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -22,9 +43,52 @@ pub struct QueryOptions {
 pub struct BuildsQueryOptions {
     status: Option<String>,
     compare_to: Option<usize>,
+    significance: Option<f64>,
+    min_effect_size: Option<f64>,
+    /// Name of the pinned baseline to compare to
+    baseline: Option<String>,
+    /// Compare to the merged last N successful builds of the branch
+    rolling: Option<usize>,
+    branch: Option<String>,
+    /// Prefix of the commit hash
+    commit: Option<String>,
+    /// Start of the build in unix seconds, inclusive
+    from: Option<u64>,
+    to: Option<u64>,
+    /// Only `branch` is supported
+    group_by: Option<String>,
+    limit: Option<usize>,
+    /// Build summary field to follow across the builds, see `metric_value`
+    metric: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeightRangeQueryOptions {
+    from: Option<usize>,
+    to: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NodeRankingQueryOptions {
+    /// `block_application` (default), `receive_latency` or `blocks_missed`
+    sort_by: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BaselineQueryOptions {
+    name: Option<String>,
 }
 
 impl BuildsQueryOptions {
+    fn regression_thresholds(&self) -> RegressionThresholds {
+        let default = RegressionThresholds::default();
+        RegressionThresholds {
+            significance: self.significance.unwrap_or(default.significance),
+            min_effect_size: self.min_effect_size.unwrap_or(default.min_effect_size),
+        }
+    }
+
     // fn status_filters(&self) -> Vec<String> {
     //     match &self.status {
     //         Some(s) => s.split(',').map(|s| s.to_string()).collect(),
@@ -36,6 +100,31 @@ impl BuildsQueryOptions {
             .as_ref()
             .map(|s| s.split(',').map(|s| s.to_string()).collect())
     }
+
+    /// Comma separated statuses, only the successful builds without a filter
+    fn matches_status(&self, status: &str) -> bool {
+        match &self.status {
+            Some(filter) => filter.split(',').any(|s| s == status),
+            None => status == "success",
+        }
+    }
+
+    /// Branch, commit and time range filters
+    fn matches(&self, build_info: &BuildInfo) -> bool {
+        self.branch
+            .as_ref()
+            .map_or(true, |branch| &build_info.source == branch)
+            && self
+                .commit
+                .as_ref()
+                .map_or(true, |commit| build_info.after.starts_with(commit.as_str()))
+            && self.from.map_or(true, |from| build_info.started >= from)
+            && self.to.map_or(true, |to| build_info.started <= to)
+    }
+
+    fn group_by_branch(&self) -> bool {
+        self.group_by.as_deref() == Some("branch")
+    }
 }
 
 pub async fn get_aggregated_block_receive_data(
@@ -53,12 +142,8 @@ pub async fn get_aggregated_block_receive_data(
     };
 
     if let Some(data) = ipc_storage.get(&height) {
-        let res: Vec<CpnpBlockPublicationFlattened> = data
-            .values()
-            .cloned()
-            .into_iter()
-            .map(|p| p.into())
-            .collect();
+        let res: Vec<CpnpBlockPublicationFlattened> =
+            data.values().cloned().map(|p| p.into()).collect();
         Ok(warp::reply::with_status(
             warp::reply::json(&res),
             StatusCode::OK,
@@ -85,12 +170,8 @@ pub async fn get_aggregated_block_receive_data_latest(
     };
 
     if let Some((_, data)) = ipc_storage.last_key_value() {
-        let res: Vec<CpnpBlockPublicationFlattened> = data
-            .values()
-            .cloned()
-            .into_iter()
-            .map(|p| p.into())
-            .collect();
+        let res: Vec<CpnpBlockPublicationFlattened> =
+            data.values().cloned().map(|p| p.into()).collect();
         Ok(warp::reply::with_status(
             warp::reply::json(&res),
             StatusCode::OK,
@@ -103,6 +184,64 @@ pub async fn get_aggregated_block_receive_data_latest(
     }
 }
 
+pub async fn get_propagation_graphs(
+    build_number: usize,
+    height: usize,
+    storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    let res: BTreeMap<BlockHash, PropagationGraph> = match storage.get(build_number) {
+        Ok(Some(read_storage)) => read_storage
+            .ipc_storage
+            .get(&height)
+            .map(|blocks| {
+                blocks
+                    .iter()
+                    .map(|(block_hash, publication)| {
+                        (
+                            block_hash.clone(),
+                            PropagationGraph::from(&publication.graph),
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+        _ => BTreeMap::new(),
+    };
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&res),
+        StatusCode::OK,
+    ))
+}
+
+pub async fn get_propagation_graph_dot(
+    build_number: usize,
+    height: usize,
+    block_hash: String,
+    storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    let dot = if let Ok(Some(read_storage)) = storage.get(build_number) {
+        read_storage
+            .ipc_storage
+            .get(&height)
+            .and_then(|blocks| blocks.get(&block_hash))
+            .map(|publication| to_dot(&publication.graph))
+    } else {
+        None
+    };
+
+    match dot {
+        Some(dot) => Ok(warp::reply::with_status(
+            warp::reply::with_header(dot, "content-type", "text/vnd.graphviz"),
+            StatusCode::OK,
+        )),
+        None => Ok(warp::reply::with_status(
+            warp::reply::with_header(String::new(), "content-type", "text/vnd.graphviz"),
+            StatusCode::NOT_FOUND,
+        )),
+    }
+}
+
 pub async fn get_aggregated_block_trace_data(
     build_number: usize,
     height: usize,
@@ -118,13 +257,8 @@ pub async fn get_aggregated_block_trace_data(
     };
 
     if let Some(data) = block_trace_storage.get(&height) {
-        let res: Vec<BlockTraceAggregatorReport> = data
-            .inner()
-            .values()
-            .cloned()
-            .into_iter()
-            .flatten()
-            .collect();
+        let res: Vec<BlockTraceAggregatorReport> =
+            data.inner().values().flatten().cloned().collect();
         Ok(warp::reply::with_status(
             warp::reply::json(&res),
             StatusCode::OK,
@@ -151,13 +285,8 @@ pub async fn get_aggregated_block_trace_data_latest(
     };
 
     if let Some((_, data)) = block_trace_storage.last_key_value() {
-        let res: Vec<BlockTraceAggregatorReport> = data
-            .inner()
-            .values()
-            .cloned()
-            .into_iter()
-            .flatten()
-            .collect();
+        let res: Vec<BlockTraceAggregatorReport> =
+            data.inner().values().flatten().cloned().collect();
         Ok(warp::reply::with_status(
             warp::reply::json(&res),
             StatusCode::OK,
@@ -248,7 +377,7 @@ pub async fn aggregate_cross_validations_handler(
         .take(count)
         .collect();
 
-    let res = aggregate_cross_validations(n_validations);
+    let res = aggregate_cross_validations(n_validations, options.validation_thresholds());
 
     Ok(warp::reply::with_status(
         warp::reply::json(&res),
@@ -278,70 +407,189 @@ pub async fn get_build_summaries(
     options: BuildsQueryOptions,
     storage: AggregatorStorage,
 ) -> Result<impl warp::Reply, warp::reject::Rejection> {
-    match storage.get_values() {
-        Ok(values) => {
-            let final_res = match options.status_filters() {
-                Some(filter) => {
-                    let res: Vec<BuildStorage> = values
-                        .into_iter()
-                        .rev()
-                        .filter(|build| filter.contains(&build.build_info.status))
-                        .map(|mut build| {
-                            build.include_times();
-                            build
-                        })
-                        .collect();
-                    let last_value = res.last().cloned().unwrap_or_default();
-                    // calculate deltas
-                    let mut final_res: Vec<BuildStorage> = res
-                        .into_iter()
-                        .tuple_windows::<(BuildStorage, BuildStorage)>()
-                        .map(|(mut w0, w1)| {
-                            w0.calculate_deltas(&w1);
-                            w0
-                        })
-                        .collect();
-                    // add the last value as is (nothing to comapre to)
-                    final_res.push(last_value);
-                    final_res
-                }
+    let values = match storage.get_values() {
+        Ok(values) => values,
+        _ => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Vec::<BuildStorage>::new()),
+                StatusCode::OK,
+            ))
+        }
+    };
+
+    let status_filters = options.status_filters();
+    let filtered = values.iter().rev().filter(|build| {
+        options.matches(&build.build_info) && options.matches_status(&build.build_info.status)
+    });
+
+    if options.group_by_branch() {
+        let mut by_branch: BTreeMap<String, Vec<BuildStorage>> = BTreeMap::new();
+        for build in filtered {
+            by_branch
+                .entry(build.build_info.source.clone())
+                .or_default()
+                .push(build.clone());
+        }
+        let final_res: BTreeMap<String, Vec<BuildStorage>> = by_branch
+            .into_iter()
+            .map(|(branch, builds)| {
                 // Without status filter, return the two most recent successfull builds
-                None => {
-                    let res: Vec<BuildStorage> = values
-                        .into_iter()
-                        .rev()
-                        .filter(|build| build.build_info.status == "success")
-                        .take(2)
-                        .map(|mut build| {
-                            build.include_times();
-                            build
-                        })
-                        .collect();
-                    let last_value = res.last().cloned().unwrap_or_default();
-                    // calculate deltas
-                    let mut final_res: Vec<BuildStorage> = res
-                        .into_iter()
-                        .tuple_windows::<(BuildStorage, BuildStorage)>()
-                        .map(|(mut w0, w1)| {
-                            w0.calculate_deltas(&w1);
-                            w0
-                        })
-                        .collect();
-                    // add the last value as is (nothing to comapre to)
-                    final_res.push(last_value);
-                    final_res
-                }
-            };
+                let builds = match status_filters {
+                    Some(_) => builds,
+                    None => builds.into_iter().take(2).collect(),
+                };
+                (branch, compare_builds(builds, &values, &options))
+            })
+            .collect();
 
-            Ok(warp::reply::with_status(
-                warp::reply::json(&final_res),
-                StatusCode::OK,
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&final_res),
+            StatusCode::OK,
+        ));
+    }
+
+    let res: Vec<BuildStorage> = match status_filters {
+        Some(_) => filtered.cloned().collect(),
+        // Without status filter, return the two most recent successfull builds
+        None => filtered.take(2).cloned().collect(),
+    };
+
+    let final_res = compare_builds(res, &values, &options);
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&final_res),
+        StatusCode::OK,
+    ))
+}
+
+/// Key metrics of the filtered builds in chronological order, grouped by branch
+pub async fn get_build_trend(
+    options: BuildsQueryOptions,
+    storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    let mut trend: BTreeMap<String, Vec<BuildTrendPoint>> = BTreeMap::new();
+
+    for build in storage.get_values().unwrap_or_default().iter() {
+        if options.matches(&build.build_info) && options.matches_status(&build.build_info.status) {
+            trend
+                .entry(build.build_info.source.clone())
+                .or_default()
+                .push(build.into());
+        }
+    }
+
+    if let Some(limit) = options.limit {
+        for points in trend.values_mut() {
+            let skip = points.len().saturating_sub(limit);
+            points.drain(..skip);
+        }
+    }
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&trend),
+        StatusCode::OK,
+    ))
+}
+
+/// A single summary metric of the filtered builds in chronological order, same filters as `/builds`
+pub async fn get_trends(
+    options: BuildsQueryOptions,
+    storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    let metric = match &options.metric {
+        Some(metric) => metric,
+        None => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&"Missing metric"),
+                StatusCode::BAD_REQUEST,
             ))
         }
-        _ => Ok(warp::reply::with_status(
-            warp::reply::json(&Vec::<BuildStorage>::new()),
+    };
+    let builds = storage.get_values().unwrap_or_default();
+    let filtered = builds.iter().filter(|build| {
+        options.matches(&build.build_info) && options.matches_status(&build.build_info.status)
+    });
+
+    match trend_series(filtered, metric, options.limit) {
+        Some(series) => Ok(warp::reply::with_status(
+            warp::reply::json(&series),
             StatusCode::OK,
         )),
+        None => Ok(warp::reply::with_status(
+            warp::reply::json(&format!("Unknown metric: {metric}")),
+            StatusCode::BAD_REQUEST,
+        )),
+    }
+}
+
+/// Compares each build to the requested baseline, or to the next older build in the list if none was requested
+fn compare_builds(
+    builds: Vec<BuildStorage>,
+    all_builds: &[BuildStorage],
+    options: &BuildsQueryOptions,
+) -> Vec<BuildStorage> {
+    let thresholds = options.regression_thresholds();
+    let builds: Vec<BuildStorage> = builds
+        .into_iter()
+        .map(|mut build| {
+            build.include_times();
+            build
+        })
+        .collect();
+
+    if options.baseline.is_some() || options.rolling.is_some() {
+        return builds
+            .into_iter()
+            .map(|mut build| {
+                if let Some((baseline, comparison)) =
+                    requested_baseline(&build, all_builds, options)
+                {
+                    build.calculate_deltas(&baseline, comparison, thresholds);
+                }
+                build
+            })
+            .collect();
+    }
+
+    let last_value = builds.last().cloned().unwrap_or_default();
+    // calculate deltas
+    let mut final_res: Vec<BuildStorage> = builds
+        .into_iter()
+        .tuple_windows::<(BuildStorage, BuildStorage)>()
+        .map(|(mut w0, w1)| {
+            let comparison = ComparisonBaseline::Previous {
+                build_number: w1.build_info.number,
+            };
+            w0.calculate_deltas(&w1, comparison, thresholds);
+            w0
+        })
+        .collect();
+    // add the last value as is (nothing to comapre to)
+    final_res.push(last_value);
+    final_res
+}
+
+/// Resolves the named or the rolling baseline from the options, `all_builds` are expected in ascending order
+fn requested_baseline(
+    build: &BuildStorage,
+    all_builds: &[BuildStorage],
+    options: &BuildsQueryOptions,
+) -> Option<(BuildStorage, ComparisonBaseline)> {
+    if let Some(name) = &options.baseline {
+        find_named_baseline(all_builds, name).map(|baseline| {
+            let comparison = ComparisonBaseline::Named {
+                name: name.clone(),
+                build_number: baseline.build_info.number,
+            };
+            (baseline.clone(), comparison)
+        })
+    } else if let Some(count) = options.rolling {
+        let rolling = rolling_baseline_builds(all_builds, build, count);
+        let build_numbers = rolling.iter().map(|b| b.build_info.number).collect();
+        BuildStorage::rolling_baseline(&rolling)
+            .map(|baseline| (baseline, ComparisonBaseline::Rolling { build_numbers }))
+    } else {
+        None
     }
 }
 
@@ -350,35 +598,157 @@ pub async fn get_build_summary(
     options: BuildsQueryOptions,
     storage: AggregatorStorage,
 ) -> Result<impl warp::Reply, warp::reject::Rejection> {
-    match options.compare_to {
-        Some(compare_to) => match (storage.get(build_num), storage.get(compare_to)) {
-            (Ok(Some(mut build)), Ok(Some(build_to_compare_to))) => {
-                build.include_times();
-                build.calculate_deltas(&build_to_compare_to);
-                Ok(warp::reply::with_status(
-                    warp::reply::json(&vec![build, build_to_compare_to]),
+    let thresholds = options.regression_thresholds();
+    let (mut build, baseline) = match (options.compare_to, storage.get(build_num)) {
+        (Some(compare_to), Ok(Some(build))) => match storage.get(compare_to) {
+            Ok(Some(build_to_compare_to)) => (
+                build,
+                Some((
+                    build_to_compare_to,
+                    ComparisonBaseline::Build {
+                        build_number: compare_to,
+                    },
+                )),
+            ),
+            _ => {
+                return Ok(warp::reply::with_status(
+                    warp::reply::json(&Vec::<BuildStorage>::new()),
                     StatusCode::OK,
                 ))
             }
-            _ => Ok(warp::reply::with_status(
-                warp::reply::json(&Vec::<BuildStorage>::new()),
-                StatusCode::OK,
-            )),
         },
-        None => match storage.get(build_num) {
-            Ok(Some(mut build)) => {
-                build.include_times();
-                Ok(warp::reply::with_status(
-                    warp::reply::json(&vec![build]),
-                    StatusCode::OK,
-                ))
-            }
-            _ => Ok(warp::reply::with_status(
+        (None, Ok(Some(build))) => {
+            let baseline = storage
+                .get_values()
+                .ok()
+                .and_then(|all_builds| requested_baseline(&build, &all_builds, &options));
+            (build, baseline)
+        }
+        _ => {
+            return Ok(warp::reply::with_status(
                 warp::reply::json(&Vec::<BuildStorage>::new()),
                 StatusCode::OK,
-            )),
-        },
+            ))
+        }
+    };
+
+    build.include_times();
+    let res = match baseline {
+        Some((baseline, comparison)) => {
+            build.calculate_deltas(&baseline, comparison, thresholds);
+            vec![build, baseline]
+        }
+        None => vec![build],
+    };
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&res),
+        StatusCode::OK,
+    ))
+}
+
+/// Pins the build as the baseline under the name (the branch of the build by default), moving the name over
+/// from the build it pinned before. A build can be pinned under several names
+pub async fn pin_baseline(
+    build_num: usize,
+    options: BaselineQueryOptions,
+    authorized: bool,
+    mut storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    if !authorized {
+        return Ok(unauthorized());
+    }
+    let build = match storage.get(build_num) {
+        Ok(Some(build)) => build,
+        _ => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&"Build not found"),
+                StatusCode::NOT_FOUND,
+            ))
+        }
+    };
+    let name = options.name.unwrap_or(build.build_info.source);
+
+    let res = storage.update_all(|number, build| {
+        if *number == build_num {
+            build.baseline_names.insert(name.clone());
+        } else {
+            build.baseline_names.remove(&name);
+        }
+    });
+
+    match res {
+        Ok(()) => Ok(warp::reply::with_status(
+            warp::reply::json(&BTreeMap::from([(name, build_num)])),
+            StatusCode::OK,
+        )),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&e.to_string()),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
+
+/// Removes the name from the build, or all of its names when no name is given
+pub async fn unpin_baseline(
+    build_num: usize,
+    options: BaselineQueryOptions,
+    authorized: bool,
+    mut storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    if !authorized {
+        return Ok(unauthorized());
     }
+    let res = storage.update(build_num, |build| match &options.name {
+        Some(name) => {
+            build.baseline_names.remove(name);
+        }
+        None => build.baseline_names.clear(),
+    });
+
+    match res {
+        Ok(()) => Ok(warp::reply::with_status(
+            warp::reply::json(&build_num),
+            StatusCode::OK,
+        )),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&e.to_string()),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
+
+fn build_not_found() -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&"Build not found"), StatusCode::NOT_FOUND)
+}
+
+fn unauthorized() -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(
+        warp::reply::json(&"Missing or invalid API token"),
+        StatusCode::UNAUTHORIZED,
+    )
+}
+
+pub async fn get_baselines(
+    storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    let baselines: BTreeMap<String, usize> = storage
+        .get_values()
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|build| {
+            let number = build.build_info.number;
+            build
+                .baseline_names
+                .into_iter()
+                .map(move |name| (name, number))
+        })
+        .collect();
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&baselines),
+        StatusCode::OK,
+    ))
 }
 
 pub async fn get_block_summaries(
@@ -396,6 +766,178 @@ pub async fn get_block_summaries(
         )),
     }
 }
+
+pub async fn get_height_series(
+    build_num: usize,
+    options: HeightRangeQueryOptions,
+    storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    let heights = options.from.unwrap_or_default()..=options.to.unwrap_or(usize::MAX);
+    match storage.get(build_num) {
+        Ok(Some(build)) => Ok(warp::reply::with_status(
+            warp::reply::json(&build.height_series(heights)),
+            StatusCode::OK,
+        )),
+        _ => Ok(build_not_found()),
+    }
+}
+
+pub async fn get_snark_pool_series(
+    build_num: usize,
+    options: HeightRangeQueryOptions,
+    storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    let from = options.from.unwrap_or_default();
+    let to = options.to.unwrap_or(usize::MAX);
+    let snapshots: Vec<SnarkPoolSnapshot> = match storage.get(build_num) {
+        Ok(Some(build)) => build
+            .snark_pool
+            .into_values()
+            .filter(|snapshot| (from..=to).contains(&snapshot.height))
+            .collect(),
+        _ => return Ok(build_not_found()),
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&snapshots),
+        StatusCode::OK,
+    ))
+}
+
+pub async fn get_node_ranking(
+    build_num: usize,
+    options: NodeRankingQueryOptions,
+    storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    let sort_by = options.sort_by.as_deref().unwrap_or("block_application");
+    let profiles = match storage.get(build_num) {
+        Ok(Some(build)) => build.node_profiles(),
+        _ => return Ok(build_not_found()),
+    };
+
+    match rank_nodes(profiles, sort_by, options.limit) {
+        Some(ranking) => Ok(warp::reply::with_status(
+            warp::reply::json(&ranking),
+            StatusCode::OK,
+        )),
+        None => Ok(warp::reply::with_status(
+            warp::reply::json(&format!("Unknown sort_by: {sort_by}")),
+            StatusCode::BAD_REQUEST,
+        )),
+    }
+}
+
+pub async fn get_node_profile(
+    build_num: usize,
+    node: String,
+    storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    match storage
+        .get(build_num)
+        .ok()
+        .flatten()
+        .and_then(|build| build.node_profile(&node))
+    {
+        Some(profile) => Ok(warp::reply::with_status(
+            warp::reply::json(&profile),
+            StatusCode::OK,
+        )),
+        None => Ok(warp::reply::with_status(
+            warp::reply::json(&"Node not found"),
+            StatusCode::NOT_FOUND,
+        )),
+    }
+}
+
+pub async fn get_node_health(
+    build_num: usize,
+    node: String,
+    storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    match storage
+        .get(build_num)
+        .ok()
+        .flatten()
+        .and_then(|mut build| build.node_health.remove(&node))
+    {
+        Some(timeline) => Ok(warp::reply::with_status(
+            warp::reply::json(&timeline),
+            StatusCode::OK,
+        )),
+        None => Ok(warp::reply::with_status(
+            warp::reply::json(&"Node not found"),
+            StatusCode::NOT_FOUND,
+        )),
+    }
+}
+
+pub async fn get_sync_status_transitions(
+    build_num: usize,
+    storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    let transitions = match storage.get(build_num) {
+        Ok(Some(build)) => build.sync_status_transitions(),
+        _ => return Ok(build_not_found()),
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&transitions),
+        StatusCode::OK,
+    ))
+}
+
+pub async fn get_node_health_events(
+    build_num: usize,
+    storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    let events = match storage.get(build_num) {
+        Ok(Some(build)) => build.node_health_events(),
+        _ => return Ok(build_not_found()),
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&events),
+        StatusCode::OK,
+    ))
+}
+
+pub async fn get_fork_report(
+    build_num: usize,
+    storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    match storage.get(build_num) {
+        Ok(Some(build)) => {
+            let orphaned_blocks = build
+                .block_summaries
+                .values()
+                .filter(|summary| summary.canonical == Some(false))
+                .cloned()
+                .collect();
+            let res = ForkReport {
+                analysis: build.build_summary.fork_analysis,
+                orphaned_blocks,
+                reorgs: build.reorgs,
+                best_chain_consensus: build.best_chain_consensus,
+            };
+            Ok(warp::reply::with_status(
+                warp::reply::json(&res),
+                StatusCode::OK,
+            ))
+        }
+        _ => Ok(build_not_found()),
+    }
+}
+
+pub async fn get_clock_skews(
+    build_num: usize,
+    storage: AggregatorStorage,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+    match storage.get(build_num) {
+        Ok(Some(build)) => Ok(warp::reply::with_status(
+            warp::reply::json(&build.clock_skews),
+            StatusCode::OK,
+        )),
+        _ => Ok(build_not_found()),
+    }
+}
+
 // fn empty_json<T: Serialize + Default>(res: T) -> impl warp::Reply {
 //     warp::reply::with_status(
 //         warp::reply::json(&T::default()),
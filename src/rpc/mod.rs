@@ -5,9 +5,13 @@ use tokio::task::JoinHandle;
 
 use crate::storage::AggregatorStorage;
 
-pub fn spawn_rpc_server(rpc_port: u16, storage: AggregatorStorage) -> JoinHandle<()> {
+pub fn spawn_rpc_server(
+    rpc_port: u16,
+    api_token: Option<String>,
+    storage: AggregatorStorage,
+) -> JoinHandle<()> {
     tokio::spawn(async move {
-        let api = filters::filters(storage.clone());
+        let api = filters::filters(storage.clone(), api_token);
 
         warp::serve(api).run(([0, 0, 0, 0], rpc_port)).await;
     })
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+use super::{BuildNumber, BuildStorage};
+
+/// The build(s) a build was compared to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ComparisonBaseline {
+    /// The next older build in the requested list
+    Previous { build_number: BuildNumber },
+    /// A build explicitly requested with `compare_to`
+    Build { build_number: BuildNumber },
+    /// A build pinned as a named baseline
+    Named {
+        name: String,
+        build_number: BuildNumber,
+    },
+    /// The last successful builds of the same branch, merged together
+    Rolling { build_numbers: Vec<BuildNumber> },
+}
+
+/// Finds the build pinned under the name, expects the builds of the whole storage
+pub fn find_named_baseline<'a>(builds: &'a [BuildStorage], name: &str) -> Option<&'a BuildStorage> {
+    builds
+        .iter()
+        .find(|build| build.baseline_names.contains(name))
+}
+
+/// The last `count` successful builds of the same branch that are older than the build
+pub fn rolling_baseline_builds<'a>(
+    builds: &'a [BuildStorage],
+    build: &BuildStorage,
+    count: usize,
+) -> Vec<&'a BuildStorage> {
+    builds
+        .iter()
+        .rev()
+        .filter(|candidate| {
+            candidate.build_info.number < build.build_info.number
+                && candidate.build_info.status == "success"
+                && candidate.build_info.source == build.build_info.source
+        })
+        .take(count)
+        .collect()
+}
+
+impl BuildStorage {
+    /// Merges the builds into one that can be used as a baseline, the time distributions are merged and the
+    /// min/avg/max values combined
+    pub fn rolling_baseline(builds: &[&BuildStorage]) -> Option<BuildStorage> {
+        let count = builds.len() as f64;
+        let (first, rest) = builds.split_first()?;
+
+        let mut baseline = BuildStorage::default();
+        baseline.helpers.application_sketch = first.helpers.application_sketch.clone();
+        baseline.helpers.production_sketch = first.helpers.production_sketch.clone();
+        baseline.helpers.receive_latency_sketch = first.helpers.receive_latency_sketch.clone();
+        for build in rest {
+            baseline
+                .helpers
+                .application_sketch
+                .merge(&build.helpers.application_sketch);
+            baseline
+                .helpers
+                .production_sketch
+                .merge(&build.helpers.production_sketch);
+            baseline
+                .helpers
+                .receive_latency_sketch
+                .merge(&build.helpers.receive_latency_sketch);
+        }
+
+        let summaries = || builds.iter().map(|build| &build.build_summary);
+        let summary = &mut baseline.build_summary;
+        summary.block_application_min = summaries()
+            .map(|s| s.block_application_min)
+            .fold(f64::MAX, f64::min);
+        summary.block_application_avg =
+            summaries().map(|s| s.block_application_avg).sum::<f64>() / count;
+        summary.block_application_max = summaries()
+            .map(|s| s.block_application_max)
+            .fold(f64::MIN, f64::max);
+        summary.block_production_min = summaries()
+            .map(|s| s.block_production_min)
+            .fold(f64::MAX, f64::min);
+        summary.block_production_avg =
+            summaries().map(|s| s.block_production_avg).sum::<f64>() / count;
+        summary.block_production_max = summaries()
+            .map(|s| s.block_production_max)
+            .fold(f64::MIN, f64::max);
+        summary.receive_latency_min = summaries()
+            .map(|s| s.receive_latency_min)
+            .fold(f64::MAX, f64::min);
+        summary.receive_latency_avg =
+            summaries().map(|s| s.receive_latency_avg).sum::<f64>() / count;
+        summary.receive_latency_max = summaries()
+            .map(|s| s.receive_latency_max)
+            .fold(f64::MIN, f64::max);
+
+        summary.block_application_stats = baseline.helpers.application_sketch.statistics();
+        summary.block_production_stats = baseline.helpers.production_sketch.statistics();
+        summary.receive_latency_stats = baseline.helpers.receive_latency_sketch.statistics();
+
+        baseline.include_times();
+        Some(baseline)
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::aggregators::{BestChainConsensus, BlockHash};
+
+use super::{BlockHeight, BlockSummary, ReorgEvent};
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ForkAnalysis {
+    /// Produced blocks that ended up in the best chain
+    pub canonical_block_count: usize,
+    /// Produced blocks on a height that is settled in the best chain, but with a different hash
+    pub orphaned_block_count: usize,
+    pub orphan_rate: f64,
+    /// Number of heights where more than one block was produced
+    pub heights_with_forks: usize,
+    /// Longest run of consecutive heights that had an orphaned block
+    pub max_fork_depth: usize,
+    pub producers: BTreeMap<String, ProducerForkStats>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ProducerForkStats {
+    pub produced_block_count: usize,
+    pub canonical_block_count: usize,
+    pub orphaned_block_count: usize,
+    pub orphan_rate: f64,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ForkReport {
+    #[serde(flatten)]
+    pub analysis: ForkAnalysis,
+    pub orphaned_blocks: Vec<BlockSummary>,
+    pub reorgs: Vec<ReorgEvent>,
+    pub best_chain_consensus: BestChainConsensus,
+}
+
+/// Marks each block as canonical or orphaned. Blocks on heights not yet present in the best chain are left undecided
+pub fn mark_canonical_blocks(
+    block_summaries: &mut BTreeMap<BlockHash, BlockSummary>,
+    best_chain: &BTreeMap<BlockHeight, BlockHash>,
+) {
+    for summary in block_summaries.values_mut() {
+        summary.canonical = best_chain
+            .get(&summary.height)
+            .map(|canonical_hash| canonical_hash == &summary.block_hash);
+    }
+}
+
+pub fn analyze_forks(block_summaries: &BTreeMap<BlockHash, BlockSummary>) -> ForkAnalysis {
+    let mut analysis = ForkAnalysis::default();
+    let mut blocks_per_height: BTreeMap<BlockHeight, usize> = BTreeMap::new();
+    let mut orphan_heights: Vec<BlockHeight> = vec![];
+
+    for summary in block_summaries.values() {
+        *blocks_per_height.entry(summary.height).or_default() += 1;
+
+        let canonical = match summary.canonical {
+            Some(canonical) => canonical,
+            None => continue,
+        };
+
+        let producer_stats = summary
+            .block_producer
+            .as_ref()
+            .map(|producer| analysis.producers.entry(producer.clone()).or_default());
+
+        if canonical {
+            analysis.canonical_block_count += 1;
+        } else {
+            analysis.orphaned_block_count += 1;
+            orphan_heights.push(summary.height);
+        }
+
+        if let Some(producer_stats) = producer_stats {
+            producer_stats.produced_block_count += 1;
+            if canonical {
+                producer_stats.canonical_block_count += 1;
+            } else {
+                producer_stats.orphaned_block_count += 1;
+            }
+            producer_stats.orphan_rate = rate(
+                producer_stats.orphaned_block_count,
+                producer_stats.produced_block_count,
+            );
+        }
+    }
+
+    analysis.orphan_rate = rate(
+        analysis.orphaned_block_count,
+        analysis.canonical_block_count + analysis.orphaned_block_count,
+    );
+    analysis.heights_with_forks = blocks_per_height.values().filter(|c| **c > 1).count();
+
+    orphan_heights.sort_unstable();
+    orphan_heights.dedup();
+    analysis.max_fork_depth = longest_consecutive_run(&orphan_heights);
+
+    analysis
+}
+
+fn rate(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64
+    }
+}
+
+/// Expects sorted and deduplicated heights
+fn longest_consecutive_run(heights: &[BlockHeight]) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    let mut previous: Option<BlockHeight> = None;
+
+    for height in heights {
+        current = match previous {
+            Some(previous) if previous + 1 == *height => current + 1,
+            _ => 1,
+        };
+        longest = longest.max(current);
+        previous = Some(*height);
+    }
+
+    longest
+}
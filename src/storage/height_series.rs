@@ -0,0 +1,152 @@
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+use serde::Serialize;
+
+use super::{BlockHeight, BuildStorage};
+
+/// Aggregated values of a single height, the times are in seconds
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct HeightSeriesPoint {
+    pub height: BlockHeight,
+    pub block_count: usize,
+    /// Transactions in the blocks on the height that were not orphaned
+    pub tx_count: usize,
+    pub block_application_avg: f64,
+    pub block_application_max: f64,
+    pub block_production_avg: f64,
+    pub receive_latency_avg: f64,
+    pub receive_latency_max: f64,
+}
+
+impl BuildStorage {
+    pub fn height_series(&self, heights: RangeInclusive<BlockHeight>) -> Vec<HeightSeriesPoint> {
+        // an inverted range would panic in BTreeMap::range
+        if heights.start() > heights.end() {
+            return vec![];
+        }
+        let helpers = &self.helpers;
+
+        let mut tx_counts: BTreeMap<BlockHeight, usize> = BTreeMap::new();
+        for summary in self.block_summaries.values() {
+            if summary.canonical != Some(false) {
+                *tx_counts.entry(summary.height).or_default() +=
+                    summary.tx_count.unwrap_or_default();
+            }
+        }
+
+        helpers
+            .block_count_per_height
+            .range(heights)
+            .map(|(height, block_count)| HeightSeriesPoint {
+                height: *height,
+                block_count: *block_count,
+                tx_count: tx_counts.get(height).copied().unwrap_or_default(),
+                block_application_avg: average(
+                    helpers.application_total.get(height),
+                    helpers.application_avg_total_count.get(height),
+                ),
+                block_application_max: helpers
+                    .application_sketches
+                    .get(height)
+                    .and_then(|sketch| sketch.max())
+                    .unwrap_or_default(),
+                block_production_avg: average(
+                    helpers.production_total.get(height),
+                    helpers.production_avg_total_count.get(height),
+                ),
+                receive_latency_avg: average(
+                    helpers.receive_latencies_total.get(height),
+                    helpers.receive_latencies_avg_total_count.get(height),
+                ),
+                receive_latency_max: helpers
+                    .receive_latency_sketches
+                    .get(height)
+                    .and_then(|sketch| sketch.max())
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+fn average(total: Option<&f64>, count: Option<&usize>) -> f64 {
+    match (total, count) {
+        (Some(total), Some(count)) if *count > 0 => total / *count as f64,
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aggregators::{AggregatedBlockTraces, BlockTraceAggregatorReport},
+        nodes::RequestStats,
+        storage::BuildStorage,
+    };
+
+    fn trace(
+        height: usize,
+        node: &str,
+        block_hash: &str,
+        is_producer: bool,
+        time: f64,
+    ) -> BlockTraceAggregatorReport {
+        BlockTraceAggregatorReport {
+            height,
+            node: node.to_string(),
+            block_hash: block_hash.to_string(),
+            is_producer,
+            receive_latency: Some(if is_producer { -time } else { time / 10.0 }),
+            block_application: Some(time),
+            included_tranasction_count: is_producer.then_some(height * 10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn test_height_series() {
+        let mut storage = BuildStorage::default();
+
+        let mut height_1 = AggregatedBlockTraces::default();
+        height_1.insert(
+            "Height1Block1".to_string(),
+            vec![
+                trace(1, "prod1", "Height1Block1", true, 20.0),
+                trace(1, "node1", "Height1Block1", false, 4.0),
+            ],
+        );
+        storage.update_summary(1, &height_1, RequestStats::default());
+
+        let mut height_2 = AggregatedBlockTraces::default();
+        height_2.insert(
+            "Height2Block1".to_string(),
+            vec![
+                trace(2, "prod1", "Height2Block1", true, 30.0),
+                trace(2, "node1", "Height2Block1", false, 10.0),
+            ],
+        );
+        height_2.insert(
+            "Height2Block2".to_string(),
+            vec![
+                trace(2, "prod2", "Height2Block2", true, 25.0),
+                trace(2, "node1", "Height2Block2", false, 6.0),
+            ],
+        );
+        storage.best_chain.insert(1, "Height1Block1".to_string());
+        storage.best_chain.insert(2, "Height2Block2".to_string());
+        storage.update_summary(2, &height_2, RequestStats::default());
+
+        // the orphaned block transactions are not counted
+        let series = storage.height_series(2..=10);
+        assert_eq!(1, series.len());
+        assert_eq!(2, series[0].height);
+        assert_eq!(2, series[0].block_count);
+        assert_eq!(20, series[0].tx_count);
+        assert_eq!(10.0, series[0].block_application_max);
+        assert_eq!(8.0, series[0].block_application_avg);
+        assert_eq!(1.0, series[0].receive_latency_max);
+
+        assert_eq!(2, storage.height_series(0..=10).len());
+        assert!(storage.height_series(2..=1).is_empty());
+    }
+}
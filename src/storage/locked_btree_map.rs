@@ -28,6 +28,34 @@ impl<K: Ord + Clone, V: Clone> LockedBTreeMap<K, V> {
             })
     }
 
+    /// Modifies the value in place while holding the write lock, does nothing if the key is not present
+    pub fn update<F: FnOnce(&mut V)>(&mut self, key: K, f: F) -> Result<(), AggregatorError> {
+        self.inner
+            .write()
+            .map(|mut write_locked_storage| {
+                if let Some(value) = write_locked_storage.get_mut(&key) {
+                    f(value);
+                }
+            })
+            .map_err(|e| AggregatorError::StorageError {
+                reason: e.to_string(),
+            })
+    }
+
+    /// Modifies all the values in place while holding the write lock
+    pub fn update_all<F: FnMut(&K, &mut V)>(&mut self, mut f: F) -> Result<(), AggregatorError> {
+        self.inner
+            .write()
+            .map(|mut write_locked_storage| {
+                for (key, value) in write_locked_storage.iter_mut() {
+                    f(key, value);
+                }
+            })
+            .map_err(|e| AggregatorError::StorageError {
+                reason: e.to_string(),
+            })
+    }
+
     pub fn get(&self, key: K) -> Result<Option<V>, AggregatorError> {
         self.inner
             .read()
@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 pub mod locked_btree_map;
 pub use locked_btree_map::*;
@@ -6,12 +6,50 @@ pub use locked_btree_map::*;
 pub mod remote;
 pub use remote::*;
 
+pub mod fork_analysis;
+pub use fork_analysis::*;
+
+pub mod reorgs;
+pub use reorgs::*;
+
+pub mod statistics;
+pub use statistics::*;
+
+pub mod sketch;
+pub use sketch::*;
+
+pub mod regression;
+pub use regression::*;
+
+pub mod baselines;
+pub use baselines::*;
+
+pub mod trends;
+pub use trends::*;
+
+pub mod height_series;
+pub use height_series::*;
+
+pub mod node_profiles;
+pub use node_profiles::*;
+
+pub mod node_health;
+pub use node_health::*;
+
+pub mod snark_pool;
+pub use snark_pool::*;
+
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    aggregators::{AggregatedBlockTraces, BlockHash, CpnpBlockPublication},
+    aggregators::{
+        estimate_clock_skews, summarize_propagation, AggregatedBlockTraces, BestChainConsensus,
+        BlockHash, BlockTraceAggregatorReport, ClockSkews, CpnpBlockPublication,
+        PropagationSummary,
+    },
     cross_validation::ValidationReport,
-    nodes::{GlobalSlot, RequestStats},
+    nodes::{DiscardedCommands, GlobalSlot, RequestStats, TraceSource},
 };
 
 pub type IpcAggregatorStorage = BTreeMap<usize, BTreeMap<BlockHash, CpnpBlockPublication>>;
@@ -25,6 +63,11 @@ pub type AggregatorStorage = LockedBTreeMap<BuildNumber, BuildStorage>;
 
 pub type BlockHeight = usize;
 
+/// Number of quantiles the time distributions are sampled at in the build summaries
+const DISTRIBUTION_POINT_COUNT: usize = 100;
+/// Outlier flags a node needs to be reported as a repeated offender
+const REPEATED_OUTLIER_COUNT: usize = 3;
+
 #[derive(Debug, Clone, Serialize)]
 pub struct BuildStorage {
     #[serde(skip)]
@@ -43,6 +86,18 @@ pub struct BuildStorage {
     pub block_summaries: BTreeMap<BlockHash, BlockSummary>,
     #[serde(skip)]
     pub best_chain: BTreeMap<BlockHeight, BlockHash>,
+    #[serde(skip)]
+    pub reorgs: Vec<ReorgEvent>,
+    #[serde(skip)]
+    pub best_chain_consensus: BestChainConsensus,
+    #[serde(skip)]
+    pub clock_skews: ClockSkews,
+    #[serde(skip)]
+    pub node_health: NodeHealthTimelines,
+    #[serde(skip)]
+    pub snark_pool: SnarkPoolStorage,
+    /// Names the build is pinned under as a baseline for comparisons
+    pub baseline_names: BTreeSet<String>,
 }
 
 impl From<BuildStorage> for BuildStorageDump {
@@ -56,12 +111,19 @@ impl From<BuildStorage> for BuildStorageDump {
             block_summaries: value.block_summaries,
             helpers: value.helpers,
             best_chain: value.best_chain,
+            reorgs: value.reorgs,
+            best_chain_consensus: value.best_chain_consensus,
+            clock_skews: value.clock_skews,
+            node_health: value.node_health,
+            snark_pool: value.snark_pool,
+            baseline_names: value.baseline_names,
         }
     }
 }
 
 impl From<BuildStorageDump> for BuildStorage {
-    fn from(value: BuildStorageDump) -> Self {
+    fn from(mut value: BuildStorageDump) -> Self {
+        value.helpers.migrate_legacy_times();
         Self {
             ipc_storage: value.ipc_storage,
             trace_storage: value.trace_storage,
@@ -71,23 +133,75 @@ impl From<BuildStorageDump> for BuildStorage {
             block_summaries: value.block_summaries,
             helpers: value.helpers,
             best_chain: value.best_chain,
+            reorgs: value.reorgs,
+            best_chain_consensus: value.best_chain_consensus,
+            clock_skews: value.clock_skews,
+            node_health: value.node_health,
+            snark_pool: value.snark_pool,
+            baseline_names: value.baseline_names,
         }
     }
 }
 
 impl BuildStorage {
-    pub fn store_data(
+    pub fn store_data(&mut self, height: usize, traces: AggregatedBlockTraces) {
+        let _ = self.trace_storage.insert(height, traces);
+    }
+
+    pub fn store_debugger_data(
         &mut self,
         height: usize,
-        traces: AggregatedBlockTraces,
         ipc_data: BTreeMap<BlockHash, CpnpBlockPublication>,
         cross_validation_report: BTreeMap<BlockHash, ValidationReport>,
     ) {
-        let _ = self.trace_storage.insert(height, traces);
         let _ = self.ipc_storage.insert(height, ipc_data);
         let _ = self
             .cross_validation_storage
             .insert(height, cross_validation_report);
+
+        self.build_summary.propagation = summarize_propagation(
+            self.ipc_storage
+                .values()
+                .flat_map(|blocks| blocks.values())
+                .filter_map(|block| block.graph_info.as_ref())
+                .map(|info| &info.propagation),
+        );
+
+        self.clock_skews = estimate_clock_skews(
+            self.ipc_storage.values().flat_map(|blocks| blocks.values()),
+            self.cross_validation_storage
+                .values()
+                .flat_map(|reports| reports.values()),
+        );
+    }
+
+    /// Moves over the data owned by the debugger worker, so a stale copy of the build does not overwrite it
+    pub fn take_debugger_data(&mut self, other: &mut BuildStorage) {
+        self.ipc_storage = std::mem::take(&mut other.ipc_storage);
+        self.cross_validation_storage = std::mem::take(&mut other.cross_validation_storage);
+        self.clock_skews = std::mem::take(&mut other.clock_skews);
+        self.build_summary.propagation = std::mem::take(&mut other.build_summary.propagation);
+        self.build_summary.debugger_request_count = other.build_summary.debugger_request_count;
+        self.build_summary.debugger_request_timeout_count =
+            other.build_summary.debugger_request_timeout_count;
+        self.build_summary.debugger_failures =
+            std::mem::take(&mut other.build_summary.debugger_failures);
+    }
+
+    pub fn update_debugger_stats(
+        &mut self,
+        request_stats: RequestStats,
+        failures: &BTreeMap<String, String>,
+    ) {
+        self.build_summary.debugger_request_count += request_stats.request_count;
+        self.build_summary.debugger_request_timeout_count += request_stats.request_timeout_count;
+        for tag in failures.keys() {
+            *self
+                .build_summary
+                .debugger_failures
+                .entry(tag.clone())
+                .or_default() += 1;
+        }
     }
 
     pub fn update_summary(
@@ -104,16 +218,18 @@ impl BuildStorage {
             self.helpers.tx_count_per_block.insert(block_hash, tx_count);
         }
 
+        for (block_hash, discarded) in block_traces.discarded_commands_per_block() {
+            self.helpers
+                .discarded_commands_per_block
+                .insert(block_hash, discarded);
+        }
+
+        self.update_outlier_counts();
+
         self.build_summary.request_timeout_count += request_stats.request_timeout_count;
         self.build_summary.request_count += request_stats.request_count;
 
-        self.build_summary.tx_count = self
-            .helpers
-            .tx_count_per_block
-            .iter()
-            .filter(|(k, _)| self.best_chain.iter().any(|(_, hash)| &hash == k))
-            .map(|(_, v)| v)
-            .sum();
+        self.update_canonical_data();
 
         let application_times: Vec<f64> = block_traces.application_times();
 
@@ -131,6 +247,7 @@ impl BuildStorage {
         let production_max = f64_max(&production_times);
 
         let receive_latencies_sum: f64 = receive_latencies.iter().sum();
+        let receive_latencies_count = receive_latencies.len();
         let receive_latencies_min = f64_min(&receive_latencies);
         let receive_latencies_max = f64_max(&receive_latencies);
 
@@ -139,15 +256,12 @@ impl BuildStorage {
         let production_measurement_count: usize = block_traces.production_count();
 
         // store the aggregated values
-        self.helpers
-            .application_times
-            .insert(height, application_times);
-        self.helpers
-            .production_times
-            .insert(height, production_times);
-        self.helpers
-            .receive_latencies
-            .insert(height, receive_latencies);
+        self.helpers.update_sketches(
+            height,
+            QuantileSketch::from_samples(&application_times),
+            QuantileSketch::from_samples(&production_times),
+            QuantileSketch::from_samples(&receive_latencies),
+        );
 
         self.helpers
             .application_avg_total_count
@@ -161,10 +275,10 @@ impl BuildStorage {
         self.helpers
             .production_total
             .insert(height, production_time_sum);
-        // the count is same as the application ones (optimization: remove this helper map and use the application map?)
+        // catchup and reconstructed blocks are excluded from latencies, so the count can differ from the application one
         self.helpers
             .receive_latencies_avg_total_count
-            .insert(height, application_measurement_count);
+            .insert(height, receive_latencies_count);
         self.helpers
             .receive_latencies_total
             .insert(height, receive_latencies_sum);
@@ -205,19 +319,89 @@ impl BuildStorage {
         self.build_summary.receive_latency_max =
             receive_latencies_max.max(self.build_summary.receive_latency_max);
         self.build_summary.receive_latency_avg = self.helpers.get_latencies_average();
+
+        self.build_summary.block_application_stats = self.helpers.application_sketch.statistics();
+        self.build_summary.block_production_stats = self.helpers.production_sketch.statistics();
+        self.build_summary.receive_latency_stats = self.helpers.receive_latency_sketch.statistics();
+    }
+
+    /// Recounts the outlier flags from the block summaries, as the traces of a height are aggregated repeatedly
+    pub fn update_outlier_counts(&mut self) {
+        let mut outlier_counts: BTreeMap<String, OutlierCounts> = BTreeMap::new();
+        for timing in self
+            .block_summaries
+            .values()
+            .flat_map(|summary| summary.peer_timings.iter())
+            .filter(|timing| timing.application_outlier || timing.receive_latency_outlier)
+        {
+            let counts = outlier_counts.entry(timing.node.clone()).or_default();
+            counts.application += timing.application_outlier as usize;
+            counts.receive_latency += timing.receive_latency_outlier as usize;
+        }
+
+        self.build_summary.repeated_outlier_nodes = outlier_counts
+            .iter()
+            .filter(|(_, counts)| counts.total() >= REPEATED_OUTLIER_COUNT)
+            .sorted_by_key(|(_, counts)| std::cmp::Reverse(counts.total()))
+            .map(|(node, _)| node.clone())
+            .collect();
+        self.build_summary.outlier_counts = outlier_counts;
+    }
+
+    /// Recomputes everything that depends on the best chain
+    pub fn update_canonical_data(&mut self) {
+        self.build_summary.tx_count = self
+            .helpers
+            .tx_count_per_block
+            .iter()
+            .filter(|(k, _)| self.best_chain.iter().any(|(_, hash)| &hash == k))
+            .map(|(_, v)| v)
+            .sum();
+
+        mark_canonical_blocks(&mut self.block_summaries, &self.best_chain);
+        self.build_summary.fork_analysis = analyze_forks(&self.block_summaries);
+
+        let mut discarded_commands = DiscardedCommands::default();
+        self.helpers
+            .discarded_commands_per_block
+            .iter()
+            .filter(|(k, _)| self.best_chain.iter().any(|(_, hash)| &hash == k))
+            .for_each(|(_, v)| discarded_commands += v);
+        self.build_summary.discarded_insufficient_work = discarded_commands.insufficient_work;
+        self.build_summary.discarded_insufficient_space = discarded_commands.insufficient_space;
     }
 
-    pub fn calculate_deltas(&mut self, other: &Self) {
+    /// Compares this build to the `other` (baseline) build. The regression flags come from a statistical test of
+    /// the whole distributions, so single outliers do not mark a regression
+    pub fn calculate_deltas(
+        &mut self,
+        other: &Self,
+        baseline: ComparisonBaseline,
+        thresholds: RegressionThresholds,
+    ) {
+        self.build_summary.baseline = Some(baseline);
+
+        let report = RegressionReport::new(
+            [
+                &self.helpers.production_sketch,
+                &self.helpers.application_sketch,
+                &self.helpers.receive_latency_sketch,
+            ],
+            [
+                &other.helpers.production_sketch,
+                &other.helpers.application_sketch,
+                &other.helpers.receive_latency_sketch,
+            ],
+            thresholds,
+        );
+
         self.build_summary.block_application_avg_delta =
             other.build_summary.block_application_avg - self.build_summary.block_application_avg;
         self.build_summary.block_application_max_delta =
             other.build_summary.block_application_max - self.build_summary.block_application_max;
         self.build_summary.block_application_min_delta =
             other.build_summary.block_application_min - self.build_summary.block_application_min;
-        self.build_summary.block_application_regression = self
-            .build_summary
-            .block_application_max_delta
-            .is_sign_positive();
+        self.build_summary.block_application_regression = report.block_application.regression;
 
         self.build_summary.block_production_avg_delta =
             other.build_summary.block_production_avg - self.build_summary.block_production_avg;
@@ -225,10 +409,7 @@ impl BuildStorage {
             other.build_summary.block_production_max - self.build_summary.block_production_max;
         self.build_summary.block_production_min_delta =
             other.build_summary.block_production_min - self.build_summary.block_production_min;
-        self.build_summary.block_production_regression = self
-            .build_summary
-            .block_production_max_delta
-            .is_sign_positive();
+        self.build_summary.block_production_regression = report.block_production.regression;
 
         self.build_summary.receive_latency_avg_delta =
             other.build_summary.receive_latency_avg - self.build_summary.receive_latency_avg;
@@ -236,40 +417,45 @@ impl BuildStorage {
             other.build_summary.receive_latency_max - self.build_summary.receive_latency_max;
         self.build_summary.receive_latency_min_delta =
             other.build_summary.receive_latency_min - self.build_summary.receive_latency_min;
-        self.build_summary.receive_latency_regression = self
-            .build_summary
-            .receive_latency_max_delta
-            .is_sign_positive();
+        self.build_summary.receive_latency_regression = report.receive_latency.regression;
 
-        self.build_summary.application_times_previous =
-            other.build_summary.application_times.clone();
-        self.build_summary.production_times_previous = other.build_summary.production_times.clone();
-        self.build_summary.receive_latencies_previous =
-            other.build_summary.receive_latencies.clone();
+        self.build_summary.regression_report = report;
+
+        self.build_summary.block_application_stats_delta = self
+            .build_summary
+            .block_application_stats
+            .delta(&other.build_summary.block_application_stats);
+        self.build_summary.block_production_stats_delta = self
+            .build_summary
+            .block_production_stats
+            .delta(&other.build_summary.block_production_stats);
+        self.build_summary.receive_latency_stats_delta = self
+            .build_summary
+            .receive_latency_stats
+            .delta(&other.build_summary.receive_latency_stats);
+
+        self.build_summary.application_distribution_previous =
+            other.build_summary.application_distribution.clone();
+        self.build_summary.production_distribution_previous =
+            other.build_summary.production_distribution.clone();
+        self.build_summary.receive_latency_distribution_previous =
+            other.build_summary.receive_latency_distribution.clone();
     }
 
+    /// Fills in the time distributions, sampled from the sketches so the response size does not grow with the build
     pub fn include_times(&mut self) {
-        self.build_summary.application_times = self
+        self.build_summary.application_distribution = self
             .helpers
-            .application_times
-            .values()
-            .flatten()
-            .cloned()
-            .collect();
-        self.build_summary.production_times = self
+            .application_sketch
+            .quantile_points(DISTRIBUTION_POINT_COUNT);
+        self.build_summary.production_distribution = self
             .helpers
-            .production_times
-            .values()
-            .flatten()
-            .cloned()
-            .collect();
-        self.build_summary.receive_latencies = self
+            .production_sketch
+            .quantile_points(DISTRIBUTION_POINT_COUNT);
+        self.build_summary.receive_latency_distribution = self
             .helpers
-            .receive_latencies
-            .values()
-            .flatten()
-            .cloned()
-            .collect();
+            .receive_latency_sketch
+            .quantile_points(DISTRIBUTION_POINT_COUNT);
     }
 }
 
@@ -283,6 +469,18 @@ pub struct BuildStorageDump {
     pub block_summaries: BTreeMap<BlockHash, BlockSummary>,
     pub helpers: BuildSummaryHelpers,
     pub best_chain: BTreeMap<BlockHeight, BlockHash>,
+    #[serde(default)]
+    pub reorgs: Vec<ReorgEvent>,
+    #[serde(default)]
+    pub best_chain_consensus: BestChainConsensus,
+    #[serde(default)]
+    pub clock_skews: ClockSkews,
+    #[serde(default)]
+    pub node_health: NodeHealthTimelines,
+    #[serde(default)]
+    pub snark_pool: SnarkPoolStorage,
+    #[serde(default)]
+    pub baseline_names: BTreeSet<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -294,8 +492,30 @@ pub struct BlockSummary {
     pub max_receive_latency: f64,
     pub date_time: Option<f64>,
     pub block_producer: Option<String>,
+    #[serde(default)]
+    pub proof_count: Option<usize>,
+    #[serde(default)]
+    pub winner: Option<String>,
+    #[serde(default)]
+    pub coinbase_receiver: Option<String>,
+    #[serde(default)]
+    pub discarded_commands: Option<DiscardedCommands>,
     pub block_producer_nodes: Vec<String>,
+    /// None until the height is included in the best chain
+    #[serde(default)]
+    pub canonical: Option<bool>,
+    /// Nodes that received the block through gossip, or produced it
     pub peer_timings: Vec<PeerTiming>,
+    /// Nodes that got the block through catchup instead of gossip
+    #[serde(default)]
+    pub catchup_count: usize,
+    #[serde(default)]
+    pub catchup_timings: Vec<PeerTiming>,
+    /// Nodes that reconstructed the block instead of receiving it through gossip
+    #[serde(default)]
+    pub reconstruct_count: usize,
+    #[serde(default)]
+    pub reconstruct_timings: Vec<PeerTiming>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -303,6 +523,40 @@ pub struct PeerTiming {
     pub node: String,
     pub block_processing_time: Option<f64>,
     pub receive_latency: Option<f64>,
+    #[serde(default)]
+    pub source: Option<TraceSource>,
+    /// The application time is an outlier among the nodes on the same height
+    #[serde(default)]
+    pub application_outlier: bool,
+    /// The receive latency is an outlier among the nodes on the same height
+    #[serde(default)]
+    pub receive_latency_outlier: bool,
+}
+
+impl From<&BlockTraceAggregatorReport> for PeerTiming {
+    fn from(value: &BlockTraceAggregatorReport) -> Self {
+        Self {
+            node: value.node.clone(),
+            block_processing_time: value.block_application,
+            receive_latency: value.receive_latency,
+            source: value.source.clone(),
+            application_outlier: false,
+            receive_latency_outlier: false,
+        }
+    }
+}
+
+/// How many blocks a node was flagged as an outlier on
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutlierCounts {
+    pub application: usize,
+    pub receive_latency: usize,
+}
+
+impl OutlierCounts {
+    pub fn total(&self) -> usize {
+        self.application + self.receive_latency
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -310,6 +564,12 @@ pub struct BuildSummary {
     pub block_count: usize,
     pub cannonical_block_count: usize,
     pub tx_count: usize,
+    /// Commands discarded from canonical blocks, because there was not enough snark work
+    #[serde(default)]
+    pub discarded_insufficient_work: usize,
+    /// Commands discarded from canonical blocks, because there was not enough space in the block
+    #[serde(default)]
+    pub discarded_insufficient_space: usize,
     pub block_production_min: f64,
     pub block_production_avg: f64,
     pub block_production_max: f64,
@@ -319,9 +579,19 @@ pub struct BuildSummary {
     pub receive_latency_min: f64,
     pub receive_latency_avg: f64,
     pub receive_latency_max: f64,
+    /// Deprecated and no longer filled, the raw times are not stored anymore. See `application_distribution`
     pub application_times: Vec<f64>,
+    /// Deprecated and no longer filled, see `production_distribution`
     pub production_times: Vec<f64>,
+    /// Deprecated and no longer filled, see `receive_latency_distribution`
     pub receive_latencies: Vec<f64>,
+    /// The application times sampled at `DISTRIBUTION_POINT_COUNT` evenly spaced quantiles
+    #[serde(default)]
+    pub application_distribution: Vec<f64>,
+    #[serde(default)]
+    pub production_distribution: Vec<f64>,
+    #[serde(default)]
+    pub receive_latency_distribution: Vec<f64>,
     pub block_production_min_delta: f64,
     pub block_production_avg_delta: f64,
     pub block_production_max_delta: f64,
@@ -331,14 +601,66 @@ pub struct BuildSummary {
     pub receive_latency_min_delta: f64,
     pub receive_latency_avg_delta: f64,
     pub receive_latency_max_delta: f64,
+    /// Deprecated and no longer filled, see `application_distribution_previous`
     pub application_times_previous: Vec<f64>,
+    /// Deprecated and no longer filled, see `production_distribution_previous`
     pub production_times_previous: Vec<f64>,
+    /// Deprecated and no longer filled, see `receive_latency_distribution_previous`
     pub receive_latencies_previous: Vec<f64>,
+    #[serde(default)]
+    pub application_distribution_previous: Vec<f64>,
+    #[serde(default)]
+    pub production_distribution_previous: Vec<f64>,
+    #[serde(default)]
+    pub receive_latency_distribution_previous: Vec<f64>,
     pub block_production_regression: bool,
     pub block_application_regression: bool,
     pub receive_latency_regression: bool,
+    /// What the deltas and the regression report were calculated against
+    #[serde(default)]
+    pub baseline: Option<ComparisonBaseline>,
+    #[serde(default)]
+    pub regression_report: RegressionReport,
+    #[serde(default)]
+    pub block_production_stats: PercentileStatistics,
+    #[serde(default)]
+    pub block_application_stats: PercentileStatistics,
+    #[serde(default)]
+    pub receive_latency_stats: PercentileStatistics,
+    #[serde(default)]
+    pub block_production_stats_delta: PercentileStatistics,
+    #[serde(default)]
+    pub block_application_stats_delta: PercentileStatistics,
+    #[serde(default)]
+    pub receive_latency_stats_delta: PercentileStatistics,
     pub request_count: usize,
     pub request_timeout_count: usize,
+    #[serde(default)]
+    pub debugger_request_count: usize,
+    #[serde(default)]
+    pub debugger_request_timeout_count: usize,
+    /// Number of polling rounds each debugger failed to respond in
+    #[serde(default)]
+    pub debugger_failures: BTreeMap<String, usize>,
+    #[serde(default)]
+    pub fork_analysis: ForkAnalysis,
+    #[serde(default)]
+    pub reorg_count: usize,
+    #[serde(default)]
+    pub max_reorg_depth: usize,
+    /// Nodes whose best chain tip diverged from the majority on the last check
+    #[serde(default)]
+    pub best_chain_divergent_nodes: Vec<String>,
+    #[serde(default)]
+    pub propagation: PropagationSummary,
+    /// Outlier flags per node, over all the blocks of the build
+    #[serde(default)]
+    pub outlier_counts: BTreeMap<String, OutlierCounts>,
+    /// Nodes flagged as outliers on at least `REPEATED_OUTLIER_COUNT` blocks, the most flagged first
+    #[serde(default)]
+    pub repeated_outlier_nodes: Vec<String>,
+    #[serde(default)]
+    pub snark_pool: SnarkPoolSummary,
     // #[serde(skip)]
     // pub avg_total_count: usize,
     // #[serde(skip)]
@@ -364,17 +686,103 @@ pub struct BuildSummaryHelpers {
     /// Total, to calculate average online
     pub receive_latencies_total: BTreeMap<BlockHeight, f64>,
 
-    // times for histograms
-    pub application_times: BTreeMap<BlockHeight, Vec<f64>>,
-    pub production_times: BTreeMap<BlockHeight, Vec<f64>>,
+    // time distributions per height and for the whole build
+    #[serde(default)]
+    pub application_sketches: BTreeMap<BlockHeight, QuantileSketch>,
+    #[serde(default)]
+    pub production_sketches: BTreeMap<BlockHeight, QuantileSketch>,
+    #[serde(default)]
+    pub receive_latency_sketches: BTreeMap<BlockHeight, QuantileSketch>,
+    #[serde(default)]
+    pub application_sketch: QuantileSketch,
+    #[serde(default)]
+    pub production_sketch: QuantileSketch,
+    #[serde(default)]
+    pub receive_latency_sketch: QuantileSketch,
     pub block_count_per_height: BTreeMap<BlockHeight, usize>,
-    pub receive_latencies: BTreeMap<BlockHeight, Vec<f64>>,
+
+    /// Raw times stored by older versions, only read to convert them into sketches
+    #[serde(default, rename = "application_times", skip_serializing)]
+    pub legacy_application_times: BTreeMap<BlockHeight, Vec<f64>>,
+    #[serde(default, rename = "production_times", skip_serializing)]
+    pub legacy_production_times: BTreeMap<BlockHeight, Vec<f64>>,
+    #[serde(default, rename = "receive_latencies", skip_serializing)]
+    pub legacy_receive_latencies: BTreeMap<BlockHeight, Vec<f64>>,
 
     /// tx counts at each block
     pub tx_count_per_block: BTreeMap<BlockHash, usize>,
+    /// discarded commands at each block
+    #[serde(default)]
+    pub discarded_commands_per_block: BTreeMap<BlockHash, DiscardedCommands>,
 }
 
 impl BuildSummaryHelpers {
+    /// Stores the sketches of a height. The build wide sketches only take the difference, a height polled again
+    /// replaces its previous sketches
+    pub fn update_sketches(
+        &mut self,
+        height: BlockHeight,
+        application: QuantileSketch,
+        production: QuantileSketch,
+        receive_latency: QuantileSketch,
+    ) {
+        replace_sketch(
+            &mut self.application_sketch,
+            &mut self.application_sketches,
+            height,
+            application,
+        );
+        replace_sketch(
+            &mut self.production_sketch,
+            &mut self.production_sketches,
+            height,
+            production,
+        );
+        replace_sketch(
+            &mut self.receive_latency_sketch,
+            &mut self.receive_latency_sketches,
+            height,
+            receive_latency,
+        );
+    }
+
+    /// Recomputes the build wide sketches from the per height ones
+    pub fn merge_sketches(&mut self) {
+        self.application_sketch = merge_all(self.application_sketches.values());
+        self.production_sketch = merge_all(self.production_sketches.values());
+        self.receive_latency_sketch = merge_all(self.receive_latency_sketches.values());
+    }
+
+    /// Converts the raw times of dumps made by older versions into sketches
+    pub fn migrate_legacy_times(&mut self) {
+        let legacy = [
+            (
+                std::mem::take(&mut self.legacy_application_times),
+                &mut self.application_sketches,
+            ),
+            (
+                std::mem::take(&mut self.legacy_production_times),
+                &mut self.production_sketches,
+            ),
+            (
+                std::mem::take(&mut self.legacy_receive_latencies),
+                &mut self.receive_latency_sketches,
+            ),
+        ];
+        let mut migrated = false;
+        for (times, sketches) in legacy {
+            for (height, samples) in times {
+                migrated = true;
+                sketches
+                    .entry(height)
+                    .or_insert_with(|| QuantileSketch::from_samples(&samples));
+            }
+        }
+        if migrated {
+            self.merge_sketches();
+        }
+    }
+
     pub fn get_application_average(&self) -> f64 {
         let count: usize = self.application_avg_total_count.values().sum();
         let total: f64 = self.application_total.values().sum();
@@ -452,6 +860,12 @@ impl BuildStorage {
             block_summaries: Default::default(),
             helpers: Default::default(),
             best_chain: Default::default(),
+            reorgs: Default::default(),
+            best_chain_consensus: Default::default(),
+            clock_skews: Default::default(),
+            node_health: Default::default(),
+            snark_pool: Default::default(),
+            baseline_names: BTreeSet::new(),
         }
     }
 
@@ -474,6 +888,25 @@ impl Default for BuildStorage {
     }
 }
 
+fn replace_sketch(
+    merged: &mut QuantileSketch,
+    sketches: &mut BTreeMap<BlockHeight, QuantileSketch>,
+    height: BlockHeight,
+    sketch: QuantileSketch,
+) {
+    merged.merge(&sketch);
+    if let Some(previous) = sketches.insert(height, sketch) {
+        merged.subtract(&previous, sketches.values());
+    }
+}
+
+fn merge_all<'a>(sketches: impl Iterator<Item = &'a QuantileSketch>) -> QuantileSketch {
+    sketches.fold(QuantileSketch::default(), |mut merged, sketch| {
+        merged.merge(sketch);
+        merged
+    })
+}
+
 fn f64_min(values: &[f64]) -> f64 {
     values
         .iter()
@@ -494,8 +927,14 @@ fn f64_max(values: &[f64]) -> f64 {
 mod tests {
     use crate::{
         aggregators::{AggregatedBlockTraces, BlockTraceAggregatorReport},
-        nodes::RequestStats,
-        storage::BuildStorage,
+        nodes::{
+            BestChainBlock, ConsensusState, DiscardedCommands, ProtocolState, RequestStats,
+            TraceSource,
+        },
+        storage::{
+            find_named_baseline, rolling_baseline_builds, BuildStorage, BuildSummaryHelpers,
+            ComparisonBaseline, QuantileSketch,
+        },
     };
 
     #[test]
@@ -598,7 +1037,101 @@ mod tests {
         // +2 to include real node's blocks at height 0 and 1
         assert_eq!(expected + 2, storage.build_summary.cannonical_block_count);
 
-        storage.store_data(height, block_traces, Default::default(), Default::default());
+        storage.store_data(height, block_traces);
+    }
+
+    #[test]
+    fn test_summary_percentiles_and_deltas() {
+        let mut storage = BuildStorage::default();
+        let height = 1;
+        let block_hash = "Height1Block1".to_string();
+
+        let receive_trace =
+            |node: &str, receive_latency: f64, block_application: f64| BlockTraceAggregatorReport {
+                height,
+                node: node.to_string(),
+                block_hash: block_hash.clone(),
+                receive_latency: Some(receive_latency),
+                block_application: Some(block_application),
+                ..Default::default()
+            };
+
+        let mut block_traces = AggregatedBlockTraces::default();
+        block_traces.insert(
+            block_hash.clone(),
+            vec![
+                receive_trace("node1", 1.20, 12.40),
+                receive_trace("node2", 2.20, 8.40),
+            ],
+        );
+
+        storage.update_summary(height, &block_traces, RequestStats::default());
+
+        // the percentiles come from a sketch with 1% accuracy
+        let within_accuracy =
+            |expected: f64, actual: f64| (expected - actual).abs() <= expected * 0.01;
+        let stats = storage.build_summary.block_application_stats;
+        assert!(within_accuracy(8.40, stats.p50));
+        assert!(within_accuracy(12.40, stats.p90));
+        assert!(within_accuracy(12.40, stats.p99));
+        assert!(almost::equal(2.0, stats.std_dev));
+
+        // an empty build compared to this one
+        let mut previous = BuildStorage::default();
+        previous.calculate_deltas(
+            &storage,
+            ComparisonBaseline::Build { build_number: 0 },
+            Default::default(),
+        );
+        assert!(within_accuracy(
+            2.20,
+            previous.build_summary.receive_latency_stats_delta.p95
+        ));
+    }
+
+    #[test]
+    fn test_summary_time_distributions() {
+        let mut storage = BuildStorage::default();
+        let height = 1;
+        let block_hash = "Height1Block1".to_string();
+
+        let block_traces = |block_applications: &[f64]| {
+            let mut block_traces = AggregatedBlockTraces::default();
+            block_traces.insert(
+                block_hash.clone(),
+                block_applications
+                    .iter()
+                    .enumerate()
+                    .map(|(i, block_application)| BlockTraceAggregatorReport {
+                        height,
+                        node: format!("node{i}"),
+                        block_hash: block_hash.clone(),
+                        receive_latency: Some(1.0),
+                        block_application: Some(*block_application),
+                        ..Default::default()
+                    })
+                    .collect(),
+            );
+            block_traces
+        };
+
+        storage.update_summary(height, &block_traces(&[2.0, 40.0]), RequestStats::default());
+        // polling the height again replaces its samples
+        storage.update_summary(
+            height,
+            &block_traces(&[2.0, 4.0, 6.0]),
+            RequestStats::default(),
+        );
+        assert_eq!(3, storage.helpers.application_sketch.count());
+        assert_eq!(Some(6.0), storage.helpers.application_sketch.max());
+
+        // the distributions in the summary are bounded, the raw times are not filled anymore
+        storage.include_times();
+        assert_eq!(100, storage.build_summary.application_distribution.len());
+        // within the 1% accuracy of the sketch
+        let highest = storage.build_summary.application_distribution[99];
+        assert!((highest - 6.0).abs() <= 0.06);
+        assert!(storage.build_summary.application_times.is_empty());
     }
 
     #[test]
@@ -653,7 +1186,7 @@ mod tests {
         block_traces.insert(block_hash, raw_traces);
 
         storage.update_summary(height, &block_traces, RequestStats::default());
-        storage.store_data(height, block_traces, Default::default(), Default::default());
+        storage.store_data(height, block_traces);
 
         // height 2 block 1
         // set height
@@ -761,7 +1294,7 @@ mod tests {
         assert_eq!(expected, storage.build_summary.block_count);
         assert_eq!(expected + 2, storage.build_summary.cannonical_block_count);
 
-        storage.store_data(height, block_traces, Default::default(), Default::default());
+        storage.store_data(height, block_traces);
     }
 
     #[test]
@@ -827,7 +1360,7 @@ mod tests {
         block_traces.insert(block_hash, raw_traces);
 
         storage.update_summary(height, &block_traces, RequestStats::default());
-        storage.store_data(height, block_traces, Default::default(), Default::default());
+        storage.store_data(height, block_traces);
 
         // height 2 block 1
         // set height
@@ -1001,6 +1534,402 @@ mod tests {
         // TODO: rework test to mimic real traces, where there are non on height 0 and 1, for now, just add 2
         assert_eq!(height + 2, storage.build_summary.cannonical_block_count);
 
-        storage.store_data(height, block_traces, Default::default(), Default::default());
+        storage.store_data(height, block_traces);
+    }
+
+    #[test]
+    fn test_fork_analysis() {
+        let mut storage = BuildStorage::default();
+
+        let producer_trace =
+            |height: usize, block_hash: &str, producer: &str| BlockTraceAggregatorReport {
+                height,
+                node: producer.to_string(),
+                block_hash: block_hash.to_string(),
+                is_producer: true,
+                block_producer: Some(producer.to_string()),
+                block_application: Some(20.0),
+                ..Default::default()
+            };
+
+        // prod2 loses the forks on heights 2 and 3, height 4 is not yet in the best chain
+        let blocks = [
+            (1, "Height1Block1", "prod1"),
+            (2, "Height2Block1", "prod2"),
+            (2, "Height2Block2", "prod1"),
+            (3, "Height3Block1", "prod2"),
+            (3, "Height3Block2", "prod1"),
+            (4, "Height4Block1", "prod1"),
+        ];
+        for height in 1..=4 {
+            let mut block_traces = AggregatedBlockTraces::default();
+            for (_, block_hash, producer) in blocks.iter().filter(|(h, _, _)| *h == height) {
+                block_traces.insert(
+                    block_hash.to_string(),
+                    vec![producer_trace(height, block_hash, producer)],
+                );
+            }
+            storage.update_summary(height, &block_traces, RequestStats::default());
+        }
+
+        storage.best_chain.insert(1, "Height1Block1".to_string());
+        storage.best_chain.insert(2, "Height2Block2".to_string());
+        storage.best_chain.insert(3, "Height3Block2".to_string());
+        storage.update_canonical_data();
+
+        let fork_analysis = &storage.build_summary.fork_analysis;
+        assert_eq!(3, fork_analysis.canonical_block_count);
+        assert_eq!(2, fork_analysis.orphaned_block_count);
+        assert_eq!(2, fork_analysis.heights_with_forks);
+        assert_eq!(2, fork_analysis.max_fork_depth);
+        assert_eq!(0.4, fork_analysis.orphan_rate);
+        assert_eq!(
+            2,
+            fork_analysis
+                .producers
+                .get("prod2")
+                .unwrap()
+                .orphaned_block_count
+        );
+        assert_eq!(
+            Some(false),
+            storage
+                .block_summaries
+                .get("Height2Block1")
+                .unwrap()
+                .canonical
+        );
+        assert_eq!(
+            None,
+            storage
+                .block_summaries
+                .get("Height4Block1")
+                .unwrap()
+                .canonical
+        );
+    }
+
+    #[test]
+    fn test_summary_update_catchup_excluded_from_latencies() {
+        let mut storage = BuildStorage::default();
+        let height = 1;
+        let block_hash = "Height1Block1".to_string();
+
+        let trace_1 = BlockTraceAggregatorReport {
+            height,
+            node: "prod1".to_string(),
+            block_hash: block_hash.clone(),
+            source: Some(TraceSource::Internal),
+            is_producer: true,
+            receive_latency: Some(-20.40),
+            block_application: Some(20.40),
+            included_tranasction_count: Some(128),
+            ..Default::default()
+        };
+
+        let trace_2 = BlockTraceAggregatorReport {
+            height,
+            node: "node1".to_string(),
+            block_hash: block_hash.clone(),
+            source: Some(TraceSource::External),
+            is_producer: false,
+            receive_latency: Some(1.20),
+            block_application: Some(12.40),
+            ..Default::default()
+        };
+
+        let trace_3 = BlockTraceAggregatorReport {
+            height,
+            node: "node2".to_string(),
+            block_hash: block_hash.clone(),
+            source: Some(TraceSource::Catchup),
+            is_producer: false,
+            receive_latency: Some(30.0),
+            block_application: Some(8.40),
+            ..Default::default()
+        };
+
+        let trace_4 = BlockTraceAggregatorReport {
+            height,
+            node: "node3".to_string(),
+            block_hash: block_hash.clone(),
+            source: Some(TraceSource::Reconstruct),
+            is_producer: false,
+            receive_latency: Some(40.0),
+            block_application: Some(2.0),
+            ..Default::default()
+        };
+
+        let raw_traces: Vec<BlockTraceAggregatorReport> = vec![trace_1, trace_2, trace_3, trace_4];
+        let mut block_traces = AggregatedBlockTraces::default();
+        block_traces.insert(block_hash.clone(), raw_traces);
+
+        storage.update_summary(height, &block_traces, RequestStats::default());
+
+        // only the gossip receive on node1 counts towards the latencies
+        let expected = 1.20;
+        assert_eq!(expected, storage.build_summary.receive_latency_min);
+        assert_eq!(expected, storage.build_summary.receive_latency_max);
+        assert_eq!(expected, storage.build_summary.receive_latency_avg);
+
+        // catchup and reconstruct are still applications
+        let expected = 2.0;
+        assert_eq!(expected, storage.build_summary.block_application_min);
+
+        let block_summary = storage.block_summaries.get(&block_hash).unwrap();
+        assert_eq!(1.20, block_summary.max_receive_latency);
+        assert_eq!(1, block_summary.catchup_count);
+        assert_eq!("node2", block_summary.catchup_timings[0].node);
+        assert_eq!(1, block_summary.reconstruct_count);
+        assert_eq!("node3", block_summary.reconstruct_timings[0].node);
+        // the catchup and reconstruct timings are reported on their own only
+        let peers: Vec<&str> = block_summary
+            .peer_timings
+            .iter()
+            .map(|timing| timing.node.as_str())
+            .collect();
+        assert_eq!(vec!["prod1", "node1"], peers);
+    }
+
+    #[test]
+    fn test_summary_update_proofs_and_discarded_commands() {
+        let mut storage = BuildStorage::default();
+        let height = 1;
+        let block_hash = "Height1Block1".to_string();
+
+        let trace_1 = BlockTraceAggregatorReport {
+            height,
+            node: "prod1".to_string(),
+            block_hash: block_hash.clone(),
+            is_producer: true,
+            block_application: Some(20.40),
+            included_tranasction_count: Some(128),
+            proof_count: Some(12),
+            discarded_commands: Some(DiscardedCommands {
+                insufficient_work: 3,
+                insufficient_space: 1,
+            }),
+            ..Default::default()
+        };
+
+        let trace_2 = BlockTraceAggregatorReport {
+            height,
+            node: "node1".to_string(),
+            block_hash: block_hash.clone(),
+            is_producer: false,
+            receive_latency: Some(1.20),
+            block_application: Some(12.40),
+            ..Default::default()
+        };
+
+        let mut block_traces = AggregatedBlockTraces::default();
+        block_traces.insert(block_hash.clone(), vec![trace_1, trace_2]);
+
+        storage.best_chain.insert(1, block_hash.clone());
+
+        storage.update_summary(height, &block_traces, RequestStats::default());
+
+        // only the producer knows the discarded commands, counted on the canonical blocks
+        assert_eq!(3, storage.build_summary.discarded_insufficient_work);
+        assert_eq!(1, storage.build_summary.discarded_insufficient_space);
+        let block_summary = storage.block_summaries.get(&block_hash).unwrap();
+        assert_eq!(Some(12), block_summary.proof_count);
+        assert_eq!(
+            Some(3),
+            block_summary
+                .discarded_commands
+                .as_ref()
+                .map(|d| d.insufficient_work)
+        );
+    }
+
+    #[test]
+    fn test_outlier_nodes() {
+        let mut storage = BuildStorage::default();
+
+        // node4 is slow to apply on every height
+        for height in 2..=4 {
+            let block_hash = format!("Height{height}Block1");
+            let traces = [
+                ("node1", 1.0),
+                ("node2", 1.1),
+                ("node3", 0.9),
+                ("node4", 6.0),
+            ]
+            .into_iter()
+            .map(|(node, application)| BlockTraceAggregatorReport {
+                height,
+                node: node.to_string(),
+                block_hash: block_hash.clone(),
+                receive_latency: Some(0.5),
+                block_application: Some(application),
+                ..Default::default()
+            })
+            .collect();
+            let mut block_traces = AggregatedBlockTraces::default();
+            block_traces.insert(block_hash.clone(), traces);
+            storage.update_summary(height, &block_traces, RequestStats::default());
+
+            let block_summary = storage.block_summaries.get(&block_hash).unwrap();
+            let flagged: Vec<&str> = block_summary
+                .peer_timings
+                .iter()
+                .filter(|timing| timing.application_outlier)
+                .map(|timing| timing.node.as_str())
+                .collect();
+            assert_eq!(vec!["node4"], flagged);
+            // equal latencies have no outliers
+            assert!(block_summary
+                .peer_timings
+                .iter()
+                .all(|timing| !timing.receive_latency_outlier));
+            storage.store_data(height, block_traces);
+        }
+
+        // aggregating a height again does not count its flags twice
+        let block_traces = storage.trace_storage.get(&4).cloned().unwrap();
+        storage.update_summary(4, &block_traces, RequestStats::default());
+
+        let counts = storage.build_summary.outlier_counts.get("node4").unwrap();
+        assert_eq!(3, counts.application);
+        assert_eq!(0, counts.receive_latency);
+        assert_eq!(1, storage.build_summary.outlier_counts.len());
+        assert_eq!(vec!["node4"], storage.build_summary.repeated_outlier_nodes);
+    }
+
+    fn best_chain_block(height: usize, state_hash: &str) -> BestChainBlock {
+        BestChainBlock {
+            state_hash: state_hash.to_string(),
+            protocol_state: ProtocolState {
+                previous_state_hash: Default::default(),
+                consensus_state: ConsensusState {
+                    block_height: height.to_string(),
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn test_best_chain_reorg_detection() {
+        let mut storage = BuildStorage::default();
+        storage
+            .helpers
+            .tx_count_per_block
+            .insert("Height2Block1".to_string(), 10);
+        storage
+            .helpers
+            .tx_count_per_block
+            .insert("Height2Block2".to_string(), 20);
+
+        let reorg = storage.update_best_chain(vec![
+            best_chain_block(1, "Height1Block1"),
+            best_chain_block(2, "Height2Block1"),
+        ]);
+        assert!(reorg.is_none());
+
+        // same chain again, no reorg
+        let reorg = storage.update_best_chain(vec![
+            best_chain_block(1, "Height1Block1"),
+            best_chain_block(2, "Height2Block1"),
+        ]);
+        assert!(reorg.is_none());
+
+        let reorg = storage
+            .update_best_chain(vec![
+                best_chain_block(1, "Height1Block1"),
+                best_chain_block(2, "Height2Block2"),
+                best_chain_block(3, "Height3Block1"),
+            ])
+            .unwrap();
+        assert_eq!(2, reorg.height);
+        assert_eq!("Height2Block1", reorg.old_hash);
+        assert_eq!("Height2Block2", reorg.new_hash);
+        assert_eq!(1, reorg.depth);
+
+        assert_eq!(1, storage.reorgs.len());
+        assert_eq!(1, storage.build_summary.reorg_count);
+        // the canonical tx count follows the new chain
+        assert_eq!(20, storage.build_summary.tx_count);
+    }
+
+    #[test]
+    fn test_legacy_times_migrated_to_sketches() {
+        let mut helpers: BuildSummaryHelpers = serde_json::from_value(serde_json::json!({
+            "application_avg_total_count": {},
+            "application_total": {},
+            "production_avg_total_count": {},
+            "production_total": {},
+            "receive_latencies_avg_total_count": {},
+            "receive_latencies_total": {},
+            "application_times": {"2": [1.0, 2.0], "3": [3.0]},
+            "production_times": {},
+            "receive_latencies": {"2": [0.5]},
+            "block_count_per_height": {},
+            "tx_count_per_block": {},
+        }))
+        .unwrap();
+
+        helpers.migrate_legacy_times();
+
+        assert_eq!(2, helpers.application_sketches.len());
+        assert_eq!(3, helpers.application_sketch.count());
+        assert_eq!(1, helpers.receive_latency_sketch.count());
+        assert!(helpers.legacy_application_times.is_empty());
+        // the raw times are not written back
+        let dumped = serde_json::to_value(&helpers).unwrap();
+        assert!(dumped.get("application_times").is_none());
+    }
+
+    #[test]
+    fn test_baselines() {
+        let build = |number: usize, branch: &str, status: &str, samples: &[f64]| {
+            let mut build = BuildStorage::default();
+            build.build_info.number = number;
+            build.build_info.source = branch.to_string();
+            build.build_info.status = status.to_string();
+            build
+                .helpers
+                .application_sketches
+                .insert(2, QuantileSketch::from_samples(samples));
+            build.helpers.merge_sketches();
+            build
+        };
+
+        let mut builds = vec![
+            build(1, "develop", "success", &[1.0, 2.0]),
+            build(2, "develop", "failure", &[100.0]),
+            build(3, "feature", "success", &[50.0]),
+            build(4, "develop", "success", &[3.0]),
+            build(5, "develop", "success", &[4.0]),
+        ];
+        builds[0].baseline_names.insert("release".to_string());
+        builds[0].baseline_names.insert("develop".to_string());
+
+        assert_eq!(
+            Some(1),
+            find_named_baseline(&builds, "release").map(|b| b.build_info.number)
+        );
+        // a build keeps all the names it is pinned under
+        assert_eq!(
+            Some(1),
+            find_named_baseline(&builds, "develop").map(|b| b.build_info.number)
+        );
+
+        // only the older successful builds of the same branch
+        let rolling = rolling_baseline_builds(&builds, &builds[4], 5);
+        let numbers: Vec<usize> = rolling.iter().map(|b| b.build_info.number).collect();
+        assert_eq!(vec![4, 1], numbers);
+
+        let baseline = BuildStorage::rolling_baseline(&rolling).unwrap();
+        assert_eq!(3, baseline.helpers.application_sketch.count());
+
+        let mut current = builds[4].clone();
+        let comparison = ComparisonBaseline::Rolling {
+            build_numbers: numbers,
+        };
+        current.calculate_deltas(&baseline, comparison.clone(), Default::default());
+        assert_eq!(Some(comparison), current.build_summary.baseline);
+
+        assert!(BuildStorage::rolling_baseline(&[]).is_none());
     }
 }
@@ -0,0 +1,312 @@
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::nodes::{ConsensusTime, DaemonMetrics, DaemonStatusDataSlim};
+
+use super::{BlockHeight, BuildStorage};
+
+/// Node health timelines keyed by node tag
+pub type NodeHealthTimelines = BTreeMap<String, NodeHealthTimeline>;
+
+/// Samples kept per node, the oldest ones are dropped first. The transitions and events are always kept
+const MAX_NODE_STATUS_SAMPLES: usize = 2000;
+/// Blocks a node can be behind the highest block received in the cluster before it is reported as lagging
+const MAX_BLOCK_LENGTH_LAG: usize = 2;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct NodeHealthTimeline {
+    /// The keys from the latest sample, they do not change while the node runs
+    #[serde(default)]
+    pub block_production_keys: Vec<String>,
+    pub samples: Vec<NodeStatusSample>,
+    pub transitions: Vec<SyncStatusTransition>,
+    #[serde(default)]
+    pub events: Vec<NodeHealthEvent>,
+}
+
+/// The daemon status of a node from one polling round
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct NodeStatusSample {
+    /// Unix timestamp (seconds) of the polling round
+    pub timestamp: u64,
+    /// The most recent produced block height at the time
+    pub height: BlockHeight,
+    pub sync_status: String,
+    pub transaction_pool_size: usize,
+    pub snark_pool_size: usize,
+    pub metrics: DaemonMetrics,
+    #[serde(default)]
+    pub uptime_secs: Option<u64>,
+    #[serde(default)]
+    pub peer_count: usize,
+    #[serde(default)]
+    pub highest_block_length_received: Option<usize>,
+    /// How far the highest block received is behind the highest one in the cluster
+    #[serde(default)]
+    pub blocks_behind: Option<usize>,
+    #[serde(default)]
+    pub catchup_status: Option<Vec<String>>,
+    #[serde(default)]
+    pub consensus_time: Option<ConsensusTime>,
+}
+
+/// A change of the sync status between two consecutive samples, e.g. `SYNCED -> CATCHUP`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncStatusTransition {
+    pub node: String,
+    pub timestamp: u64,
+    pub height: BlockHeight,
+    pub from: String,
+    pub to: String,
+}
+
+/// A failed health check, recorded when the node starts failing it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeHealthEvent {
+    pub node: String,
+    pub timestamp: u64,
+    pub height: BlockHeight,
+    #[serde(flatten)]
+    pub kind: NodeHealthEventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NodeHealthEventKind {
+    /// The uptime went down since the previous sample
+    Restarted {
+        uptime_secs: u64,
+    },
+    NoPeers,
+    /// The node is more than `MAX_BLOCK_LENGTH_LAG` blocks behind the highest block in the cluster
+    Lagging {
+        blocks_behind: usize,
+    },
+}
+
+impl NodeHealthTimeline {
+    fn add(&mut self, node: &str, sample: NodeStatusSample) {
+        let last = self.samples.last();
+
+        if let Some(last) = last {
+            if last.sync_status != sample.sync_status {
+                self.transitions.push(SyncStatusTransition {
+                    node: node.to_string(),
+                    timestamp: sample.timestamp,
+                    height: sample.height,
+                    from: last.sync_status.clone(),
+                    to: sample.sync_status.clone(),
+                });
+            }
+        }
+
+        let is_lagging = |sample: &NodeStatusSample| matches!(sample.blocks_behind, Some(behind) if behind > MAX_BLOCK_LENGTH_LAG);
+        let mut kinds = vec![];
+        if let (Some(previous), Some(current)) =
+            (last.and_then(|last| last.uptime_secs), sample.uptime_secs)
+        {
+            if current < previous {
+                kinds.push(NodeHealthEventKind::Restarted {
+                    uptime_secs: current,
+                });
+            }
+        }
+        if sample.peer_count == 0 && last.map_or(true, |last| last.peer_count > 0) {
+            kinds.push(NodeHealthEventKind::NoPeers);
+        }
+        if is_lagging(&sample) && !last.map_or(false, is_lagging) {
+            kinds.push(NodeHealthEventKind::Lagging {
+                blocks_behind: sample.blocks_behind.unwrap_or_default(),
+            });
+        }
+        self.events
+            .extend(kinds.into_iter().map(|kind| NodeHealthEvent {
+                node: node.to_string(),
+                timestamp: sample.timestamp,
+                height: sample.height,
+                kind,
+            }));
+
+        self.samples.push(sample);
+        let overflow = self.samples.len().saturating_sub(MAX_NODE_STATUS_SAMPLES);
+        self.samples.drain(..overflow);
+    }
+}
+
+impl BuildStorage {
+    /// Appends the node statuses collected in a polling round to the timelines
+    pub fn record_node_statuses(
+        &mut self,
+        height: BlockHeight,
+        node_infos: &BTreeMap<String, DaemonStatusDataSlim>,
+    ) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let highest_block_length = node_infos
+            .values()
+            .filter_map(|info| info.daemon_status.highest_block_length_received)
+            .max();
+
+        for (node, info) in node_infos {
+            let status = &info.daemon_status;
+            let timeline = self.node_health.entry(node.clone()).or_default();
+            timeline.block_production_keys = status.block_production_keys.clone();
+            timeline.add(
+                node,
+                NodeStatusSample {
+                    timestamp,
+                    height,
+                    sync_status: status.sync_status.clone(),
+                    transaction_pool_size: status.metrics.transaction_pool_size,
+                    snark_pool_size: info.snark_pool,
+                    metrics: status.metrics.clone(),
+                    uptime_secs: status.uptime_secs,
+                    peer_count: info.peer_count,
+                    highest_block_length_received: status.highest_block_length_received,
+                    blocks_behind: highest_block_length
+                        .zip(status.highest_block_length_received)
+                        .map(|(highest, length)| highest.saturating_sub(length)),
+                    catchup_status: status.catchup_status.clone(),
+                    consensus_time: status.consensus_time_now.clone(),
+                },
+            );
+        }
+    }
+
+    /// The sync status transitions of all the nodes, in the order they were detected
+    pub fn sync_status_transitions(&self) -> Vec<SyncStatusTransition> {
+        let mut transitions: Vec<SyncStatusTransition> = self
+            .node_health
+            .values()
+            .flat_map(|timeline| timeline.transitions.iter().cloned())
+            .collect();
+        transitions.sort_by_key(|transition| (transition.timestamp, transition.height));
+        transitions
+    }
+
+    /// The failed health checks of all the nodes, in the order they were detected
+    pub fn node_health_events(&self) -> Vec<NodeHealthEvent> {
+        let mut events: Vec<NodeHealthEvent> = self
+            .node_health
+            .values()
+            .flat_map(|timeline| timeline.events.iter().cloned())
+            .collect();
+        events.sort_by_key(|event| (event.timestamp, event.height));
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        nodes::{AddrsAndPorts, DaemonMetrics, DaemonStatus, DaemonStatusDataSlim, Peer},
+        storage::BuildStorage,
+    };
+
+    use super::NodeHealthEventKind;
+
+    fn node_info(
+        sync_status: &str,
+        snark_pool: usize,
+        block_length: usize,
+        uptime_secs: u64,
+        peer_count: usize,
+    ) -> DaemonStatusDataSlim {
+        DaemonStatusDataSlim {
+            daemon_status: DaemonStatus {
+                addrs_and_ports: AddrsAndPorts {
+                    external_ip: "127.0.0.1".to_string(),
+                    peer: Peer {
+                        peer_id: "peer".to_string(),
+                    },
+                },
+                sync_status: sync_status.to_string(),
+                metrics: DaemonMetrics {
+                    transaction_pool_size: 7,
+                    ..Default::default()
+                },
+                uptime_secs: Some(uptime_secs),
+                block_production_keys: vec!["B62q".to_string()],
+                peers: vec![],
+                highest_block_length_received: Some(block_length),
+                catchup_status: None,
+                consensus_time_now: None,
+            },
+            snark_pool,
+            peer_count,
+            snark_pool_provers: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_node_health_timeline() {
+        let mut build = BuildStorage::default();
+        // node2 restarts and loses its peers on height 3, then falls behind on height 4
+        let rounds = [
+            (2, "SYNCED", "SYNCED", 4, 100),
+            (3, "SYNCED", "CATCHUP", 6, 10),
+            (3, "SYNCED", "CATCHUP", 6, 20),
+            (4, "SYNCED", "SYNCED", 5, 30),
+        ];
+        for (height, node1_status, node2_status, node2_length, node2_uptime) in rounds {
+            let node_infos = BTreeMap::from([
+                (
+                    "node1".to_string(),
+                    node_info(node1_status, height, height * 2, height as u64 * 100, 5),
+                ),
+                (
+                    "node2".to_string(),
+                    node_info(
+                        node2_status,
+                        height,
+                        node2_length,
+                        node2_uptime,
+                        (height != 3) as usize,
+                    ),
+                ),
+            ]);
+            build.record_node_statuses(height, &node_infos);
+        }
+
+        let node1 = build.node_health.get("node1").unwrap();
+        assert_eq!(4, node1.samples.len());
+        assert!(node1.transitions.is_empty());
+        assert_eq!(4, node1.samples[3].snark_pool_size);
+        assert_eq!(7, node1.samples[3].transaction_pool_size);
+        assert_eq!(vec!["B62q"], node1.block_production_keys);
+        assert!(node1.events.is_empty());
+
+        let transitions = build.sync_status_transitions();
+        assert_eq!(2, transitions.len());
+        assert_eq!("node2", transitions[0].node);
+        assert_eq!(3, transitions[0].height);
+        assert_eq!("SYNCED", transitions[0].from);
+        assert_eq!("CATCHUP", transitions[0].to);
+        assert_eq!(4, transitions[1].height);
+        assert_eq!("SYNCED", transitions[1].to);
+
+        let events = build.node_health_events();
+        assert_eq!(
+            vec![
+                NodeHealthEventKind::Restarted { uptime_secs: 10 },
+                NodeHealthEventKind::NoPeers,
+                NodeHealthEventKind::Lagging { blocks_behind: 3 },
+            ],
+            events
+                .into_iter()
+                .map(|event| event.kind)
+                .collect::<Vec<_>>()
+        );
+        let node2 = build.node_health.get("node2").unwrap();
+        assert_eq!(Some(3), node2.samples[3].blocks_behind);
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use crate::aggregators::{BlockHash, BlockTraceAggregatorReport};
+
+use super::{BlockHeight, BuildStorage, PercentileStatistics, QuantileSketch};
+
+/// Everything the traces tell about a single node over the whole build, the times are in seconds
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct NodeProfile {
+    pub node: String,
+    pub node_address: String,
+    /// Blocks the node has a trace for
+    pub block_count: usize,
+    /// Blocks traced by other nodes of the cluster, but not by this one
+    pub blocks_missed: usize,
+    pub block_application: TimeDistribution,
+    /// Only the blocks received through gossip
+    pub receive_latency: TimeDistribution,
+    /// The sync status at the first height and at each height where it changed
+    pub sync_status_history: Vec<SyncStatusChange>,
+    pub pool_sizes: Vec<PoolSizesPoint>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct TimeDistribution {
+    pub count: u64,
+    pub avg: f64,
+    pub max: f64,
+    #[serde(flatten)]
+    pub stats: PercentileStatistics,
+}
+
+impl From<&QuantileSketch> for TimeDistribution {
+    fn from(sketch: &QuantileSketch) -> Self {
+        Self {
+            count: sketch.count(),
+            avg: sketch.mean(),
+            max: sketch.max().unwrap_or_default(),
+            stats: sketch.statistics(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct SyncStatusChange {
+    pub height: BlockHeight,
+    pub sync_status: String,
+}
+
+/// Pool sizes reported with the latest trace of the height
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct PoolSizesPoint {
+    pub height: BlockHeight,
+    pub snark_pool_size: usize,
+    pub transaction_pool_size: usize,
+}
+
+/// A node profile without the time series, for ranking the nodes
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct NodeRankingEntry {
+    pub node: String,
+    pub block_count: usize,
+    pub blocks_missed: usize,
+    pub block_application: TimeDistribution,
+    pub receive_latency: TimeDistribution,
+}
+
+impl From<NodeProfile> for NodeRankingEntry {
+    fn from(profile: NodeProfile) -> Self {
+        Self {
+            node: profile.node,
+            block_count: profile.block_count,
+            blocks_missed: profile.blocks_missed,
+            block_application: profile.block_application,
+            receive_latency: profile.receive_latency,
+        }
+    }
+}
+
+#[derive(Default)]
+struct NodeAccumulator {
+    node_address: String,
+    blocks: BTreeSet<BlockHash>,
+    application_sketch: QuantileSketch,
+    receive_latency_sketch: QuantileSketch,
+    sync_status_history: Vec<SyncStatusChange>,
+    pool_sizes: Vec<PoolSizesPoint>,
+}
+
+impl NodeAccumulator {
+    fn add(&mut self, height: BlockHeight, trace: &BlockTraceAggregatorReport) {
+        self.node_address = trace.node_address.clone();
+        // every node gets a report for every block, the ones it has no trace for are the missed ones
+        if trace.source.is_some() {
+            self.blocks.insert(trace.block_hash.clone());
+        }
+
+        // same selection as the build summary, the producer times are production times
+        if !trace.is_producer {
+            if let Some(application) = trace.block_application {
+                self.application_sketch.add(application);
+            }
+            if let Some(latency) = trace.receive_latency.filter(|_| trace.is_gossip_receive()) {
+                self.receive_latency_sketch.add(latency);
+            }
+        }
+
+        if self
+            .sync_status_history
+            .last()
+            .map_or(true, |change| change.sync_status != trace.sync_status)
+        {
+            self.sync_status_history.push(SyncStatusChange {
+                height,
+                sync_status: trace.sync_status.clone(),
+            });
+        }
+
+        let pool_sizes = PoolSizesPoint {
+            height,
+            snark_pool_size: trace.snark_pool_size,
+            transaction_pool_size: trace.transaction_pool_size,
+        };
+        match self.pool_sizes.last_mut() {
+            Some(last) if last.height == height => *last = pool_sizes,
+            _ => self.pool_sizes.push(pool_sizes),
+        }
+    }
+}
+
+impl BuildStorage {
+    pub fn node_profiles(&self) -> Vec<NodeProfile> {
+        let mut nodes: BTreeMap<String, NodeAccumulator> = BTreeMap::new();
+        let mut total_block_count = 0;
+
+        for (height, block_traces) in self.trace_storage.iter() {
+            total_block_count += block_traces.unique_block_count();
+
+            // chronological order, so the histories end with the latest values
+            let mut traces: Vec<&BlockTraceAggregatorReport> = block_traces.traces().collect();
+            traces.sort_by(|a, b| {
+                a.date_time
+                    .unwrap_or_default()
+                    .total_cmp(&b.date_time.unwrap_or_default())
+            });
+            for trace in traces {
+                nodes
+                    .entry(trace.node.clone())
+                    .or_default()
+                    .add(*height, trace);
+            }
+        }
+
+        nodes
+            .into_iter()
+            .map(|(node, accumulator)| NodeProfile {
+                node,
+                node_address: accumulator.node_address,
+                block_count: accumulator.blocks.len(),
+                blocks_missed: total_block_count.saturating_sub(accumulator.blocks.len()),
+                block_application: (&accumulator.application_sketch).into(),
+                receive_latency: (&accumulator.receive_latency_sketch).into(),
+                sync_status_history: accumulator.sync_status_history,
+                pool_sizes: accumulator.pool_sizes,
+            })
+            .collect()
+    }
+
+    pub fn node_profile(&self, node: &str) -> Option<NodeProfile> {
+        self.node_profiles()
+            .into_iter()
+            .find(|profile| profile.node == node)
+    }
+}
+
+/// Orders the nodes from the slowest, by the p90 of the times or by the missed blocks.
+/// Returns None for an unknown `sort_by`
+pub fn rank_nodes(
+    mut profiles: Vec<NodeProfile>,
+    sort_by: &str,
+    limit: Option<usize>,
+) -> Option<Vec<NodeRankingEntry>> {
+    let key: fn(&NodeProfile) -> f64 = match sort_by {
+        "block_application" => |profile| profile.block_application.stats.p90,
+        "receive_latency" => |profile| profile.receive_latency.stats.p90,
+        "blocks_missed" => |profile| profile.blocks_missed as f64,
+        _ => return None,
+    };
+
+    profiles.sort_by(|a, b| key(b).total_cmp(&key(a)));
+    Some(
+        profiles
+            .into_iter()
+            .take(limit.unwrap_or(usize::MAX))
+            .map(NodeRankingEntry::from)
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aggregators::{AggregatedBlockTraces, BlockTraceAggregatorReport},
+        nodes::TraceSource,
+        storage::BuildStorage,
+    };
+
+    use super::rank_nodes;
+
+    fn trace(
+        node: &str,
+        block_hash: &str,
+        date_time: f64,
+        application: f64,
+        sync_status: &str,
+        snark_pool_size: usize,
+    ) -> BlockTraceAggregatorReport {
+        BlockTraceAggregatorReport {
+            node: node.to_string(),
+            block_hash: block_hash.to_string(),
+            source: Some(TraceSource::External),
+            date_time: Some(date_time),
+            block_application: Some(application),
+            receive_latency: Some(application / 2.0),
+            sync_status: sync_status.to_string(),
+            snark_pool_size,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_node_profiles() {
+        let mut build = BuildStorage::default();
+
+        let mut height_2 = AggregatedBlockTraces::default();
+        height_2.insert(
+            "block_a".to_string(),
+            vec![
+                trace("node1", "block_a", 2.0, 1.0, "SYNCED", 4),
+                trace("node2", "block_a", 1.0, 3.0, "CATCHUP", 1),
+            ],
+        );
+        height_2.insert(
+            "block_b".to_string(),
+            vec![
+                trace("node1", "block_b", 1.0, 1.0, "SYNCED", 3),
+                // node2 did not trace block_b, but still gets a report from its node info
+                BlockTraceAggregatorReport {
+                    node: "node2".to_string(),
+                    block_hash: "block_b".to_string(),
+                    sync_status: "CATCHUP".to_string(),
+                    snark_pool_size: 1,
+                    ..Default::default()
+                },
+            ],
+        );
+        build.store_data(2, height_2);
+
+        let mut height_3 = AggregatedBlockTraces::default();
+        height_3.insert(
+            "block_c".to_string(),
+            vec![
+                trace("node1", "block_c", 3.0, 2.0, "SYNCED", 5),
+                trace("node2", "block_c", 3.0, 5.0, "SYNCED", 2),
+            ],
+        );
+        build.store_data(3, height_3);
+
+        let node1 = build.node_profile("node1").unwrap();
+        assert_eq!(3, node1.block_count);
+        assert_eq!(0, node1.blocks_missed);
+        assert_eq!(3, node1.block_application.count);
+        assert_eq!(2.0, node1.block_application.max);
+        assert_eq!(1, node1.sync_status_history.len());
+        // the latest trace of the height wins
+        assert_eq!(4, node1.pool_sizes[0].snark_pool_size);
+        assert_eq!(5, node1.pool_sizes[1].snark_pool_size);
+
+        let node2 = build.node_profile("node2").unwrap();
+        assert_eq!(2, node2.block_count);
+        assert_eq!(1, node2.blocks_missed);
+        assert_eq!(2, node2.sync_status_history.len());
+        assert_eq!(3, node2.sync_status_history[1].height);
+        assert_eq!("SYNCED", node2.sync_status_history[1].sync_status);
+        assert!(build.node_profile("node3").is_none());
+
+        let ranking = rank_nodes(build.node_profiles(), "block_application", Some(1)).unwrap();
+        assert_eq!(1, ranking.len());
+        assert_eq!("node2", ranking[0].node);
+        let ranking = rank_nodes(build.node_profiles(), "blocks_missed", None).unwrap();
+        assert_eq!("node2", ranking[0].node);
+        assert!(rank_nodes(build.node_profiles(), "no_such_key", None).is_none());
+    }
+}
@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+
+use super::QuantileSketch;
+
+const SIGNIFICANCE_DEFAULT: f64 = 0.05;
+const MIN_EFFECT_SIZE_DEFAULT: f64 = 0.05;
+/// Below this many samples in either build the test is not run
+const MIN_SAMPLE_COUNT: u64 = 5;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RegressionThresholds {
+    /// The p-value under which a difference between the distributions is significant
+    pub significance: f64,
+    /// Minimal relative change of the median to be reported as a regression or an improvement
+    pub min_effect_size: f64,
+}
+
+impl Default for RegressionThresholds {
+    fn default() -> Self {
+        Self {
+            significance: SIGNIFICANCE_DEFAULT,
+            min_effect_size: MIN_EFFECT_SIZE_DEFAULT,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RegressionReport {
+    pub thresholds: Option<RegressionThresholds>,
+    pub block_production: MetricComparison,
+    pub block_application: MetricComparison,
+    pub receive_latency: MetricComparison,
+}
+
+/// Mann-Whitney U test of the current build distribution against the baseline one
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MetricComparison {
+    pub baseline_count: u64,
+    pub current_count: u64,
+    pub baseline_median: f64,
+    pub current_median: f64,
+    /// Relative change of the median, positive means the current build is slower
+    pub relative_change: f64,
+    pub u_statistic: f64,
+    /// Two sided p-value, None when there were not enough samples
+    pub p_value: Option<f64>,
+    /// Probability that a random sample of the current build is higher than one of the baseline
+    pub probability_of_increase: f64,
+    pub significant: bool,
+    pub regression: bool,
+    pub improvement: bool,
+}
+
+impl RegressionReport {
+    pub fn new(
+        current: [&QuantileSketch; 3],
+        baseline: [&QuantileSketch; 3],
+        thresholds: RegressionThresholds,
+    ) -> Self {
+        let [production, application, latency] = current;
+        let [baseline_production, baseline_application, baseline_latency] = baseline;
+
+        Self {
+            thresholds: Some(thresholds),
+            block_production: compare_distributions(production, baseline_production, &thresholds),
+            block_application: compare_distributions(
+                application,
+                baseline_application,
+                &thresholds,
+            ),
+            receive_latency: compare_distributions(latency, baseline_latency, &thresholds),
+        }
+    }
+}
+
+pub fn compare_distributions(
+    current: &QuantileSketch,
+    baseline: &QuantileSketch,
+    thresholds: &RegressionThresholds,
+) -> MetricComparison {
+    let current_median = current.quantile(0.5);
+    let baseline_median = baseline.quantile(0.5);
+    let relative_change = if baseline_median == 0.0 {
+        0.0
+    } else {
+        (current_median - baseline_median) / baseline_median.abs()
+    };
+
+    let mut comparison = MetricComparison {
+        baseline_count: baseline.count(),
+        current_count: current.count(),
+        baseline_median,
+        current_median,
+        relative_change,
+        ..Default::default()
+    };
+
+    if current.count() < MIN_SAMPLE_COUNT || baseline.count() < MIN_SAMPLE_COUNT {
+        return comparison;
+    }
+
+    let (u_statistic, p_value) = mann_whitney_u(current, baseline);
+    let significant = p_value < thresholds.significance;
+
+    comparison.u_statistic = u_statistic;
+    comparison.p_value = Some(p_value);
+    comparison.probability_of_increase =
+        u_statistic / (current.count() as f64 * baseline.count() as f64);
+    comparison.significant = significant;
+    comparison.regression = significant && relative_change >= thresholds.min_effect_size;
+    comparison.improvement = significant && relative_change <= -thresholds.min_effect_size;
+    comparison
+}
+
+/// Returns the U statistic of the first sample and the two sided p-value, using the normal approximation with
+/// tie correction. The samples in the same sketch bucket are treated as ties.
+fn mann_whitney_u(first: &QuantileSketch, second: &QuantileSketch) -> (f64, f64) {
+    let n1 = first.count() as f64;
+    let n2 = second.count() as f64;
+    let total = n1 + n2;
+
+    let mut first_values = first.weighted_values().into_iter().peekable();
+    let mut second_values = second.weighted_values().into_iter().peekable();
+
+    let mut rank_sum = 0.0;
+    let mut tie_sum = 0.0;
+    let mut ranked = 0.0;
+
+    loop {
+        let value = match (first_values.peek(), second_values.peek()) {
+            (Some((a, _)), Some((b, _))) => a.min(*b),
+            (Some((a, _)), None) => *a,
+            (None, Some((b, _))) => *b,
+            (None, None) => break,
+        };
+        let first_count = first_values
+            .next_if(|(v, _)| *v == value)
+            .map_or(0.0, |(_, c)| c as f64);
+        let second_count = second_values
+            .next_if(|(v, _)| *v == value)
+            .map_or(0.0, |(_, c)| c as f64);
+
+        let tied = first_count + second_count;
+        let average_rank = ranked + (tied + 1.0) / 2.0;
+        rank_sum += first_count * average_rank;
+        tie_sum += tied.powi(3) - tied;
+        ranked += tied;
+    }
+
+    let u_statistic = rank_sum - n1 * (n1 + 1.0) / 2.0;
+    let mean = n1 * n2 / 2.0;
+    let variance = n1 * n2 / 12.0 * ((total + 1.0) - tie_sum / (total * (total - 1.0)));
+
+    if variance <= 0.0 {
+        return (u_statistic, 1.0);
+    }
+
+    let z = (u_statistic - mean).abs() / variance.sqrt();
+    (u_statistic, (2.0 * (1.0 - standard_normal_cdf(z))).min(1.0))
+}
+
+fn standard_normal_cdf(z: f64) -> f64 {
+    0.5 * (1.0 + erf(z / std::f64::consts::SQRT_2))
+}
+
+/// Abramowitz and Stegun 7.1.26, the error is under 1.5e-7
+fn erf(x: f64) -> f64 {
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let y = 1.0
+        - (((((1.061405429 * t - 1.453152027) * t) + 1.421413741) * t - 0.284496736) * t
+            + 0.254829592)
+            * t
+            * (-x * x).exp();
+    sign * y
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::QuantileSketch;
+
+    use super::{compare_distributions, RegressionThresholds};
+
+    #[test]
+    fn test_regression_detection() {
+        let thresholds = RegressionThresholds::default();
+        let baseline_samples: Vec<f64> = (0..50).map(|i| 1.0 + (i % 10) as f64 / 10.0).collect();
+        let baseline = QuantileSketch::from_samples(&baseline_samples);
+
+        // same distribution with a single huge outlier is not a regression
+        let mut with_outlier = baseline_samples.clone();
+        with_outlier.push(100.0);
+        let comparison = compare_distributions(
+            &QuantileSketch::from_samples(&with_outlier),
+            &baseline,
+            &thresholds,
+        );
+        assert!(!comparison.significant);
+        assert!(!comparison.regression);
+
+        // everything got 50% slower
+        let slower: Vec<f64> = baseline_samples.iter().map(|v| v * 1.5).collect();
+        let comparison = compare_distributions(
+            &QuantileSketch::from_samples(&slower),
+            &baseline,
+            &thresholds,
+        );
+        assert!(comparison.significant);
+        assert!(comparison.regression);
+        assert!(!comparison.improvement);
+        assert!(comparison.p_value.unwrap() < 0.001);
+        assert!(comparison.probability_of_increase > 0.8);
+
+        // and the other way around
+        let comparison = compare_distributions(
+            &baseline,
+            &QuantileSketch::from_samples(&slower),
+            &thresholds,
+        );
+        assert!(comparison.improvement);
+
+        // not enough samples to tell
+        let comparison = compare_distributions(
+            &QuantileSketch::from_samples(&[10.0]),
+            &baseline,
+            &thresholds,
+        );
+        assert_eq!(None, comparison.p_value);
+        assert!(!comparison.regression);
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aggregators::{BestChainConsensus, BlockHash},
+    nodes::BestChainBlock,
+};
+
+use super::{BlockHeight, BuildStorage};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReorgEvent {
+    /// The lowest height where the canonical hash changed
+    pub height: BlockHeight,
+    pub old_hash: BlockHash,
+    pub new_hash: BlockHash,
+    /// Number of canonical blocks replaced by the reorg
+    pub depth: usize,
+    /// Unix timestamp (seconds) of the detection
+    pub detected_at: u64,
+}
+
+impl BuildStorage {
+    /// Merges a freshly queried best chain into the stored one, recording a reorg when a canonical hash changes
+    pub fn update_best_chain(&mut self, best_chain: Vec<BestChainBlock>) -> Option<ReorgEvent> {
+        let mut replaced: Vec<(BlockHeight, BlockHash, BlockHash)> = vec![];
+
+        for best_chain_block in best_chain {
+            let height = best_chain_block
+                .protocol_state
+                .consensus_state
+                .block_height
+                .parse()
+                .unwrap_or_default();
+            if let Some(old_hash) = self
+                .best_chain
+                .insert(height, best_chain_block.state_hash.clone())
+            {
+                if old_hash != best_chain_block.state_hash {
+                    replaced.push((height, old_hash, best_chain_block.state_hash));
+                }
+            }
+        }
+
+        let reorg = replaced.iter().min_by_key(|(height, _, _)| *height).map(
+            |(height, old_hash, new_hash)| ReorgEvent {
+                height: *height,
+                old_hash: old_hash.clone(),
+                new_hash: new_hash.clone(),
+                depth: replaced.len(),
+                detected_at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default(),
+            },
+        );
+
+        if let Some(reorg) = &reorg {
+            self.reorgs.push(reorg.clone());
+            self.build_summary.reorg_count = self.reorgs.len();
+            self.build_summary.max_reorg_depth =
+                self.build_summary.max_reorg_depth.max(reorg.depth);
+            self.update_canonical_data();
+        }
+
+        reorg
+    }
+
+    /// Updates the best chain from the majority of the queried nodes
+    pub fn update_best_chain_consensus(
+        &mut self,
+        consensus: BestChainConsensus,
+    ) -> Option<ReorgEvent> {
+        self.build_summary.best_chain_divergent_nodes =
+            consensus.divergent_nodes.keys().cloned().collect();
+        let majority_chain = consensus.majority_chain();
+        self.best_chain_consensus = consensus;
+        self.update_best_chain(majority_chain)
+    }
+}
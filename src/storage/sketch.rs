@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::PercentileStatistics;
+
+/// Relative accuracy of the quantiles returned by the sketch
+const RELATIVE_ACCURACY: f64 = 0.01;
+/// Values closer to zero than this are counted as zero
+const MIN_MAGNITUDE: f64 = 1e-9;
+
+/// Mergeable quantile sketch with logarithmic buckets (same idea as DDSketch), the size depends on the range
+/// of the values, not on their count. The quantiles are within 1% of the exact values.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuantileSketch {
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+    zero_count: u64,
+    count: u64,
+    sum: f64,
+    sum_squares: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl QuantileSketch {
+    pub fn from_samples<'a>(samples: impl IntoIterator<Item = &'a f64>) -> Self {
+        let mut sketch = Self::default();
+        for sample in samples {
+            sketch.add(*sample);
+        }
+        sketch
+    }
+
+    pub fn add(&mut self, value: f64) {
+        if value > MIN_MAGNITUDE {
+            *self.positive.entry(bucket(value)).or_default() += 1;
+        } else if value < -MIN_MAGNITUDE {
+            *self.negative.entry(bucket(-value)).or_default() += 1;
+        } else {
+            self.zero_count += 1;
+        }
+
+        self.count += 1;
+        self.sum += value;
+        self.sum_squares += value * value;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for (key, count) in other.positive.iter() {
+            *self.positive.entry(*key).or_default() += count;
+        }
+        for (key, count) in other.negative.iter() {
+            *self.negative.entry(*key).or_default() += count;
+        }
+        self.zero_count += other.zero_count;
+        self.count += other.count;
+        self.sum += other.sum;
+        self.sum_squares += other.sum_squares;
+        self.min = match (self.min, other.min) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.max = match (self.max, other.max) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+    }
+
+    /// Takes the samples of a sketch merged into this one earlier back out. The buckets do not know the min and
+    /// max, so they are recomputed from the sketches that remain merged
+    pub fn subtract<'a>(&mut self, other: &Self, remaining: impl Iterator<Item = &'a Self>) {
+        for (buckets, other_buckets) in [
+            (&mut self.positive, &other.positive),
+            (&mut self.negative, &other.negative),
+        ] {
+            for (key, count) in other_buckets.iter() {
+                if let Some(bucket_count) = buckets.get_mut(key) {
+                    *bucket_count = bucket_count.saturating_sub(*count);
+                    if *bucket_count == 0 {
+                        buckets.remove(key);
+                    }
+                }
+            }
+        }
+        self.zero_count = self.zero_count.saturating_sub(other.zero_count);
+        self.count = self.count.saturating_sub(other.count);
+        self.sum -= other.sum;
+        self.sum_squares -= other.sum_squares;
+
+        let (min, max) = remaining.fold(
+            (None, None),
+            |(min, max): (Option<f64>, Option<f64>), sketch| {
+                (
+                    min.into_iter().chain(sketch.min).reduce(f64::min),
+                    max.into_iter().chain(sketch.max).reduce(f64::max),
+                )
+            },
+        );
+        self.min = min;
+        self.max = max;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        self.max
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Nearest rank quantile for `q` in [0, 1], 0 for an empty sketch
+    pub fn quantile(&self, q: f64) -> f64 {
+        let (min, max) = match (self.min, self.max) {
+            (Some(min), Some(max)) => (min, max),
+            _ => return 0.0,
+        };
+
+        let rank = ((q * self.count as f64).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+
+        // the negative values are ordered from the largest magnitude
+        for (key, count) in self.negative.iter().rev() {
+            seen += count;
+            if seen >= rank {
+                return (-value(*key)).clamp(min, max);
+            }
+        }
+        seen += self.zero_count;
+        if seen >= rank {
+            return 0.0;
+        }
+        for (key, count) in self.positive.iter() {
+            seen += count;
+            if seen >= rank {
+                return value(*key).clamp(min, max);
+            }
+        }
+
+        max
+    }
+
+    /// The representative value of each bucket with the number of samples in it, in ascending order
+    pub fn weighted_values(&self) -> Vec<(f64, u64)> {
+        let negative = self
+            .negative
+            .iter()
+            .rev()
+            .map(|(key, count)| (-value(*key), *count));
+        let zero = (self.zero_count > 0).then_some((0.0, self.zero_count));
+        let positive = self
+            .positive
+            .iter()
+            .map(|(key, count)| (value(*key), *count));
+
+        negative.chain(zero).chain(positive).collect()
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum / self.count as f64
+    }
+
+    /// Population standard deviation
+    pub fn std_dev(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        (self.sum_squares / self.count as f64 - mean * mean)
+            .max(0.0)
+            .sqrt()
+    }
+
+    /// The distribution sampled at evenly spaced quantiles, a bounded replacement for the raw samples
+    pub fn quantile_points(&self, point_count: usize) -> Vec<f64> {
+        if self.is_empty() {
+            return vec![];
+        }
+        (0..point_count)
+            .map(|i| self.quantile((i as f64 + 0.5) / point_count as f64))
+            .collect()
+    }
+
+    pub fn statistics(&self) -> PercentileStatistics {
+        PercentileStatistics {
+            p50: self.quantile(0.5),
+            p90: self.quantile(0.9),
+            p95: self.quantile(0.95),
+            p99: self.quantile(0.99),
+            std_dev: self.std_dev(),
+        }
+    }
+}
+
+fn gamma() -> f64 {
+    (1.0 + RELATIVE_ACCURACY) / (1.0 - RELATIVE_ACCURACY)
+}
+
+fn bucket(magnitude: f64) -> i32 {
+    (magnitude.ln() / gamma().ln()).ceil() as i32
+}
+
+/// The value in the bucket with the lowest relative error to anything that falls into it
+fn value(key: i32) -> f64 {
+    let gamma = gamma();
+    2.0 * gamma.powi(key) / (gamma + 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QuantileSketch;
+
+    fn within_accuracy(expected: f64, actual: f64) -> bool {
+        (expected - actual).abs() <= expected.abs() * 0.01
+    }
+
+    #[test]
+    fn test_sketch_quantiles_and_merge() {
+        let samples: Vec<f64> = (1..=1000).map(|i| i as f64 / 100.0).collect();
+        let (first, second) = samples.split_at(400);
+
+        let mut sketch = QuantileSketch::from_samples(first);
+        sketch.merge(&QuantileSketch::from_samples(second));
+
+        assert_eq!(1000, sketch.count());
+        assert!(within_accuracy(5.0, sketch.quantile(0.5)));
+        assert!(within_accuracy(9.9, sketch.quantile(0.99)));
+        assert_eq!(10.0, sketch.quantile(1.0));
+        assert_eq!(0.01, sketch.quantile(0.0));
+        assert!(within_accuracy(2.8867, sketch.std_dev()));
+        assert_eq!(100, sketch.quantile_points(100).len());
+
+        let mixed = QuantileSketch::from_samples(&[-2.0, 0.0, 3.0]);
+        assert!(within_accuracy(-2.0, mixed.quantile(0.1)));
+        assert_eq!(0.0, mixed.quantile(0.5));
+        assert!(within_accuracy(3.0, mixed.quantile(0.9)));
+
+        // survives the storage dump
+        let dumped = serde_json::to_string(&sketch).unwrap();
+        assert_eq!(sketch, serde_json::from_str(&dumped).unwrap());
+        let empty = serde_json::to_string(&QuantileSketch::default()).unwrap();
+        assert!(serde_json::from_str::<QuantileSketch>(&empty)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_sketch_subtract() {
+        let first = QuantileSketch::from_samples(&[1.0, 2.0, 20.0]);
+        let second = QuantileSketch::from_samples(&[3.0, 4.0]);
+
+        let mut sketch = first.clone();
+        sketch.merge(&second);
+        sketch.subtract(&first, [&second].into_iter());
+
+        assert_eq!(2, sketch.count());
+        assert_eq!(second.weighted_values(), sketch.weighted_values());
+        assert_eq!(Some(4.0), sketch.max());
+        assert!(within_accuracy(3.5, sketch.mean()));
+
+        sketch.subtract(&second, std::iter::empty());
+        assert!(sketch.is_empty());
+        assert_eq!(None, sketch.max());
+    }
+}
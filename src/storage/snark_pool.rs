@@ -0,0 +1,232 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::nodes::DaemonStatusDataSlim;
+
+use super::{BlockHeight, BuildStorage};
+
+/// Snark pool snapshots keyed by the most recent produced block height
+pub type SnarkPoolStorage = BTreeMap<BlockHeight, SnarkPoolSnapshot>;
+
+/// The snark pools of the cluster from the last polling round on a height
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnarkPoolSnapshot {
+    pub height: BlockHeight,
+    pub node_count: usize,
+    pub min_pool_size: usize,
+    pub avg_pool_size: f64,
+    pub max_pool_size: usize,
+    /// Standard deviation of the pool sizes divided by their mean, 0 when all the nodes see the same pool
+    pub pool_size_variation: f64,
+    /// Change of the average pool size per block since the previous recorded height
+    pub growth_per_block: Option<f64>,
+    /// Work count per prover in the largest pool, which is the most complete view
+    pub prover_work_counts: BTreeMap<String, usize>,
+}
+
+/// Snark work availability over the whole build
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnarkPoolSummary {
+    pub avg_pool_size: f64,
+    pub max_pool_size: usize,
+    pub avg_growth_per_block: f64,
+    pub avg_pool_size_variation: f64,
+    /// Heights where no node had any snark work in its pool
+    pub empty_pool_height_count: usize,
+    pub prover_count: usize,
+}
+
+impl SnarkPoolSnapshot {
+    fn new(height: BlockHeight, node_infos: &BTreeMap<String, DaemonStatusDataSlim>) -> Self {
+        let mut sum = 0.0;
+        let mut sum_squares = 0.0;
+        let mut min_pool_size: Option<usize> = None;
+        let mut largest_pool: Option<&DaemonStatusDataSlim> = None;
+        for info in node_infos.values() {
+            let size = info.snark_pool as f64;
+            sum += size;
+            sum_squares += size * size;
+            min_pool_size =
+                Some(min_pool_size.map_or(info.snark_pool, |min| min.min(info.snark_pool)));
+            if largest_pool.map_or(true, |largest| info.snark_pool > largest.snark_pool) {
+                largest_pool = Some(info);
+            }
+        }
+
+        let node_count = node_infos.len();
+        let avg_pool_size = sum / node_count.max(1) as f64;
+        let std_dev = (sum_squares / node_count.max(1) as f64 - avg_pool_size * avg_pool_size)
+            .max(0.0)
+            .sqrt();
+
+        Self {
+            height,
+            node_count,
+            min_pool_size: min_pool_size.unwrap_or_default(),
+            avg_pool_size,
+            max_pool_size: largest_pool.map_or(0, |largest| largest.snark_pool),
+            pool_size_variation: if avg_pool_size > 0.0 {
+                std_dev / avg_pool_size
+            } else {
+                0.0
+            },
+            growth_per_block: None,
+            prover_work_counts: largest_pool
+                .map(|largest| largest.snark_pool_provers.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl BuildStorage {
+    /// Records the snark pools of a polling round, a later round on the same height replaces the snapshot
+    pub fn record_snark_pool(
+        &mut self,
+        height: BlockHeight,
+        node_infos: &BTreeMap<String, DaemonStatusDataSlim>,
+    ) {
+        if node_infos.is_empty() {
+            return;
+        }
+
+        self.snark_pool
+            .insert(height, SnarkPoolSnapshot::new(height, node_infos));
+        // a height can be recorded between two others, so the growth of the next one changes as well
+        update_growth(&mut self.snark_pool, height);
+        if let Some(next_height) = self
+            .snark_pool
+            .range(height + 1..)
+            .next()
+            .map(|(next_height, _)| *next_height)
+        {
+            update_growth(&mut self.snark_pool, next_height);
+        }
+
+        self.build_summary.snark_pool = summarize_snark_pool(&self.snark_pool);
+    }
+}
+
+/// Sets the growth of the snapshot on the height since the previous recorded height
+fn update_growth(snark_pool: &mut SnarkPoolStorage, height: BlockHeight) {
+    let growth_per_block = match (
+        snark_pool.range(..height).next_back(),
+        snark_pool.get(&height),
+    ) {
+        (Some((previous_height, previous)), Some(snapshot)) => Some(
+            (snapshot.avg_pool_size - previous.avg_pool_size) / (height - previous_height) as f64,
+        ),
+        _ => None,
+    };
+    if let Some(snapshot) = snark_pool.get_mut(&height) {
+        snapshot.growth_per_block = growth_per_block;
+    }
+}
+
+pub fn summarize_snark_pool(snark_pool: &SnarkPoolStorage) -> SnarkPoolSummary {
+    let count = snark_pool.len().max(1) as f64;
+    let growths: Vec<f64> = snark_pool
+        .values()
+        .filter_map(|snapshot| snapshot.growth_per_block)
+        .collect();
+    let provers: BTreeSet<&String> = snark_pool
+        .values()
+        .flat_map(|snapshot| snapshot.prover_work_counts.keys())
+        .collect();
+
+    SnarkPoolSummary {
+        avg_pool_size: snark_pool
+            .values()
+            .map(|snapshot| snapshot.avg_pool_size)
+            .sum::<f64>()
+            / count,
+        max_pool_size: snark_pool
+            .values()
+            .map(|snapshot| snapshot.max_pool_size)
+            .max()
+            .unwrap_or_default(),
+        avg_growth_per_block: growths.iter().sum::<f64>() / growths.len().max(1) as f64,
+        avg_pool_size_variation: snark_pool
+            .values()
+            .map(|snapshot| snapshot.pool_size_variation)
+            .sum::<f64>()
+            / count,
+        empty_pool_height_count: snark_pool
+            .values()
+            .filter(|snapshot| snapshot.max_pool_size == 0)
+            .count(),
+        prover_count: provers.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{nodes::DaemonStatusDataSlim, storage::BuildStorage};
+
+    fn node_info(provers: &[(&str, usize)]) -> DaemonStatusDataSlim {
+        DaemonStatusDataSlim {
+            snark_pool: provers.iter().map(|(_, count)| count).sum(),
+            snark_pool_provers: provers
+                .iter()
+                .map(|(prover, count)| (prover.to_string(), *count))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_snark_pool_analytics() {
+        let mut build = BuildStorage::default();
+
+        build.record_snark_pool(
+            2,
+            &BTreeMap::from([
+                ("node1".to_string(), node_info(&[])),
+                ("node2".to_string(), node_info(&[])),
+            ]),
+        );
+        build.record_snark_pool(
+            4,
+            &BTreeMap::from([
+                (
+                    "node1".to_string(),
+                    node_info(&[("prover1", 4), ("prover2", 2)]),
+                ),
+                ("node2".to_string(), node_info(&[("prover1", 2)])),
+            ]),
+        );
+
+        let snapshot = build.snark_pool.get(&4).unwrap();
+        assert_eq!(2, snapshot.min_pool_size);
+        assert_eq!(4.0, snapshot.avg_pool_size);
+        assert_eq!(6, snapshot.max_pool_size);
+        assert!((snapshot.pool_size_variation - 0.5).abs() < 1e-9);
+        assert_eq!(Some(2.0), snapshot.growth_per_block);
+        assert_eq!(Some(&4), snapshot.prover_work_counts.get("prover1"));
+        assert_eq!(None, build.snark_pool.get(&2).unwrap().growth_per_block);
+
+        let summary = &build.build_summary.snark_pool;
+        assert_eq!(2.0, summary.avg_pool_size);
+        assert_eq!(6, summary.max_pool_size);
+        assert_eq!(2.0, summary.avg_growth_per_block);
+        assert_eq!(1, summary.empty_pool_height_count);
+        assert_eq!(2, summary.prover_count);
+
+        // a height recorded late, between the two others
+        build.record_snark_pool(
+            3,
+            &BTreeMap::from([("node1".to_string(), node_info(&[("prover1", 1)]))]),
+        );
+        assert_eq!(
+            Some(1.0),
+            build.snark_pool.get(&3).unwrap().growth_per_block
+        );
+        assert_eq!(
+            Some(3.0),
+            build.snark_pool.get(&4).unwrap().growth_per_block
+        );
+        assert_eq!(2.0, build.build_summary.snark_pool.avg_growth_per_block);
+    }
+}
@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+/// Distribution of a metric, less sensitive to outliers than the min/max values
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PercentileStatistics {
+    pub p50: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub std_dev: f64,
+}
+
+impl PercentileStatistics {
+    /// Difference of the other statistics from these, positive values mean the other ones are higher
+    pub fn delta(&self, other: &Self) -> Self {
+        Self {
+            p50: other.p50 - self.p50,
+            p90: other.p90 - self.p90,
+            p95: other.p95 - self.p95,
+            p99: other.p99 - self.p99,
+            std_dev: other.std_dev - self.std_dev,
+        }
+    }
+}
+
+/// Modified z-score above which a value is an outlier (Iglewicz and Hoaglin)
+const OUTLIER_Z_SCORE: f64 = 3.5;
+/// Fewer values do not say much about what is normal
+const MIN_OUTLIER_SAMPLE_COUNT: usize = 3;
+
+/// Nearest rank percentile, expects sorted values
+pub fn percentile(sorted: &[f64], percentile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((percentile / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+pub fn median(values: &mut [f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+/// The value above which a sample is an outlier on the slow side, based on the median absolute deviation.
+/// None when there are too few samples or most of them are equal, so the deviation is 0
+pub fn upper_outlier_bound(values: &[f64]) -> Option<f64> {
+    if values.len() < MIN_OUTLIER_SAMPLE_COUNT {
+        return None;
+    }
+    let center = median(&mut values.to_vec());
+    let mad = median(
+        &mut values
+            .iter()
+            .map(|value| (value - center).abs())
+            .collect::<Vec<_>>(),
+    );
+    // the modified z-score is 0.6745 * (x - median) / mad
+    (mad > 0.0).then(|| center + OUTLIER_Z_SCORE * mad / 0.6745)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::upper_outlier_bound;
+
+    #[test]
+    fn test_upper_outlier_bound() {
+        let times = [1.0, 1.1, 0.9, 1.2, 1.0, 4.0];
+        let bound = upper_outlier_bound(&times).unwrap();
+        assert!(bound > 1.2 && bound < 4.0);
+
+        assert_eq!(None, upper_outlier_bound(&[1.0, 5.0]));
+        assert_eq!(None, upper_outlier_bound(&[1.0, 1.0, 1.0, 5.0]));
+    }
+}
@@ -0,0 +1,265 @@
+use serde::Serialize;
+
+use crate::aggregators::PropagationSummary;
+
+use super::{
+    BuildNumber, BuildStorage, BuildSummary, ForkAnalysis, PercentileStatistics, SnarkPoolSummary,
+};
+
+/// The key metrics of a build, to follow how they evolve across the commits of a branch
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct BuildTrendPoint {
+    pub build_number: BuildNumber,
+    pub commit: String,
+    pub message: String,
+    pub started: u64,
+    pub status: String,
+    pub block_count: usize,
+    pub tx_count: usize,
+    pub block_production_avg: f64,
+    pub block_production_p90: f64,
+    pub block_application_avg: f64,
+    pub block_application_p90: f64,
+    pub receive_latency_avg: f64,
+    pub receive_latency_p90: f64,
+    pub orphan_rate: f64,
+}
+
+impl From<&BuildStorage> for BuildTrendPoint {
+    fn from(build: &BuildStorage) -> Self {
+        let info = &build.build_info;
+        let summary = &build.build_summary;
+        Self {
+            build_number: info.number,
+            commit: info.after.clone(),
+            message: info.message.clone(),
+            started: info.started,
+            status: info.status.clone(),
+            block_count: summary.block_count,
+            tx_count: summary.tx_count,
+            block_production_avg: summary.block_production_avg,
+            block_production_p90: summary.block_production_stats.p90,
+            block_application_avg: summary.block_application_avg,
+            block_application_p90: summary.block_application_stats.p90,
+            receive_latency_avg: summary.receive_latency_avg,
+            receive_latency_p90: summary.receive_latency_stats.p90,
+            orphan_rate: summary.fork_analysis.orphan_rate,
+        }
+    }
+}
+
+/// A single summary metric across builds, for charting
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct TrendSeries {
+    pub metric: String,
+    pub points: Vec<TrendPoint>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct TrendPoint {
+    pub build_number: BuildNumber,
+    pub branch: String,
+    pub commit: String,
+    pub started: u64,
+    pub value: f64,
+}
+
+/// Looks up a numeric field of the build summary by name, nested fields are separated by dots
+/// (e.g. `block_application_stats.p90`). Returns None when there is no such numeric field
+pub fn metric_value(summary: &BuildSummary, metric: &str) -> Option<f64> {
+    if let Some((group, field)) = metric.split_once('.') {
+        return match group {
+            "block_production_stats" => percentile_value(&summary.block_production_stats, field),
+            "block_application_stats" => percentile_value(&summary.block_application_stats, field),
+            "receive_latency_stats" => percentile_value(&summary.receive_latency_stats, field),
+            "block_production_stats_delta" => {
+                percentile_value(&summary.block_production_stats_delta, field)
+            }
+            "block_application_stats_delta" => {
+                percentile_value(&summary.block_application_stats_delta, field)
+            }
+            "receive_latency_stats_delta" => {
+                percentile_value(&summary.receive_latency_stats_delta, field)
+            }
+            "fork_analysis" => fork_analysis_value(&summary.fork_analysis, field),
+            "propagation" => propagation_value(&summary.propagation, field),
+            "snark_pool" => snark_pool_value(&summary.snark_pool, field),
+            _ => None,
+        };
+    }
+
+    let value = match metric {
+        "block_count" => summary.block_count as f64,
+        "cannonical_block_count" => summary.cannonical_block_count as f64,
+        "tx_count" => summary.tx_count as f64,
+        "discarded_insufficient_work" => summary.discarded_insufficient_work as f64,
+        "discarded_insufficient_space" => summary.discarded_insufficient_space as f64,
+        "block_production_min" => summary.block_production_min,
+        "block_production_avg" => summary.block_production_avg,
+        "block_production_max" => summary.block_production_max,
+        "block_application_min" => summary.block_application_min,
+        "block_application_avg" => summary.block_application_avg,
+        "block_application_max" => summary.block_application_max,
+        "receive_latency_min" => summary.receive_latency_min,
+        "receive_latency_avg" => summary.receive_latency_avg,
+        "receive_latency_max" => summary.receive_latency_max,
+        "block_production_min_delta" => summary.block_production_min_delta,
+        "block_production_avg_delta" => summary.block_production_avg_delta,
+        "block_production_max_delta" => summary.block_production_max_delta,
+        "block_application_min_delta" => summary.block_application_min_delta,
+        "block_application_avg_delta" => summary.block_application_avg_delta,
+        "block_application_max_delta" => summary.block_application_max_delta,
+        "receive_latency_min_delta" => summary.receive_latency_min_delta,
+        "receive_latency_avg_delta" => summary.receive_latency_avg_delta,
+        "receive_latency_max_delta" => summary.receive_latency_max_delta,
+        "block_production_regression" => bool_value(summary.block_production_regression),
+        "block_application_regression" => bool_value(summary.block_application_regression),
+        "receive_latency_regression" => bool_value(summary.receive_latency_regression),
+        "request_count" => summary.request_count as f64,
+        "request_timeout_count" => summary.request_timeout_count as f64,
+        "debugger_request_count" => summary.debugger_request_count as f64,
+        "debugger_request_timeout_count" => summary.debugger_request_timeout_count as f64,
+        "reorg_count" => summary.reorg_count as f64,
+        "max_reorg_depth" => summary.max_reorg_depth as f64,
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn percentile_value(stats: &PercentileStatistics, field: &str) -> Option<f64> {
+    match field {
+        "p50" => Some(stats.p50),
+        "p90" => Some(stats.p90),
+        "p95" => Some(stats.p95),
+        "p99" => Some(stats.p99),
+        "std_dev" => Some(stats.std_dev),
+        _ => None,
+    }
+}
+
+fn fork_analysis_value(analysis: &ForkAnalysis, field: &str) -> Option<f64> {
+    match field {
+        "canonical_block_count" => Some(analysis.canonical_block_count as f64),
+        "orphaned_block_count" => Some(analysis.orphaned_block_count as f64),
+        "orphan_rate" => Some(analysis.orphan_rate),
+        "heights_with_forks" => Some(analysis.heights_with_forks as f64),
+        "max_fork_depth" => Some(analysis.max_fork_depth as f64),
+        _ => None,
+    }
+}
+
+fn propagation_value(propagation: &PropagationSummary, field: &str) -> Option<f64> {
+    match field {
+        "analyzed_block_count" => Some(propagation.analyzed_block_count as f64),
+        "max_hop_count" => Some(propagation.max_hop_count as f64),
+        "avg_max_hop_count" => Some(propagation.avg_max_hop_count),
+        "max_critical_path_latency" => Some(propagation.max_critical_path_latency),
+        "avg_critical_path_latency" => Some(propagation.avg_critical_path_latency),
+        "slow_edge_count" => Some(propagation.slow_edge_count as f64),
+        _ => None,
+    }
+}
+
+fn snark_pool_value(snark_pool: &SnarkPoolSummary, field: &str) -> Option<f64> {
+    match field {
+        "avg_pool_size" => Some(snark_pool.avg_pool_size),
+        "max_pool_size" => Some(snark_pool.max_pool_size as f64),
+        "avg_growth_per_block" => Some(snark_pool.avg_growth_per_block),
+        "avg_pool_size_variation" => Some(snark_pool.avg_pool_size_variation),
+        "empty_pool_height_count" => Some(snark_pool.empty_pool_height_count as f64),
+        "prover_count" => Some(snark_pool.prover_count as f64),
+        _ => None,
+    }
+}
+
+fn bool_value(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Expects the builds in chronological order, keeps the last `limit` points
+pub fn trend_series<'a>(
+    builds: impl Iterator<Item = &'a BuildStorage>,
+    metric: &str,
+    limit: Option<usize>,
+) -> Option<TrendSeries> {
+    let mut points = vec![];
+    for build in builds {
+        points.push(TrendPoint {
+            build_number: build.build_info.number,
+            branch: build.build_info.source.clone(),
+            commit: build.build_info.after.clone(),
+            started: build.build_info.started,
+            value: metric_value(&build.build_summary, metric)?,
+        });
+    }
+
+    if let Some(limit) = limit {
+        let skip = points.len().saturating_sub(limit);
+        points.drain(..skip);
+    }
+
+    Some(TrendSeries {
+        metric: metric.to_string(),
+        points,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::{BuildStorage, BuildSummary};
+
+    use super::{metric_value, trend_series};
+
+    #[test]
+    fn test_trend_series() {
+        let summary = BuildSummary {
+            block_application_avg: 1.5,
+            tx_count: 12,
+            receive_latency_regression: true,
+            ..Default::default()
+        };
+        assert_eq!(Some(1.5), metric_value(&summary, "block_application_avg"));
+        assert_eq!(Some(12.0), metric_value(&summary, "tx_count"));
+        assert_eq!(
+            Some(1.0),
+            metric_value(&summary, "receive_latency_regression")
+        );
+        assert_eq!(
+            Some(0.0),
+            metric_value(&summary, "block_application_stats.p90")
+        );
+        assert_eq!(None, metric_value(&summary, "no_such_metric"));
+        assert_eq!(None, metric_value(&summary, "application_times"));
+        assert_eq!(None, metric_value(&summary, "block_application_stats.p42"));
+        assert_eq!(
+            Some(0.0),
+            metric_value(&summary, "fork_analysis.orphan_rate")
+        );
+        assert_eq!(
+            Some(0.0),
+            metric_value(&summary, "snark_pool.avg_pool_size")
+        );
+
+        let builds: Vec<BuildStorage> = (1..=3)
+            .map(|number| {
+                let mut build = BuildStorage::default();
+                build.build_info.number = number;
+                build.build_summary.block_count = number * 10;
+                build
+            })
+            .collect();
+
+        let series = trend_series(builds.iter(), "block_count", Some(2)).unwrap();
+        let values: Vec<(usize, f64)> = series
+            .points
+            .iter()
+            .map(|p| (p.build_number, p.value))
+            .collect();
+        assert_eq!(vec![(2, 20.0), (3, 30.0)], values);
+        assert!(trend_series(builds.iter(), "no_such_metric", None).is_none());
+    }
+}